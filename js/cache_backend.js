@@ -1,13 +1,20 @@
 import { logger } from './logger';
 
-// local cache db name
+// local cache db name prefix, each repo has its own database
 const LOCAL_CACHE_DB = 'zbox_local_cache';
 
+// object store name in local cache db
+const STORE_NAME = 'cache';
+
+// get local cache db name for a repo
+function dbName(repoId) {
+  return `${LOCAL_CACHE_DB}_${repoId}`;
+}
+
 // open cache backend database
-function openDb(storeName) {
+function openDb(repoId) {
   return new Promise((resolve, reject) => {
-    const ver = Math.floor(Date.now() / 1000);
-    let req = indexedDB.open(LOCAL_CACHE_DB, ver);
+    let req = indexedDB.open(dbName(repoId), 1);
 
     req.onerror = (event) => {
       reject('IndexedDB is forbidden to use');
@@ -15,11 +22,9 @@ function openDb(storeName) {
 
     req.onupgradeneeded = (event) => {
       let db = event.target.result;
-      if (!db.objectStoreNames.contains(storeName)) {
-        db.createObjectStore(storeName, {
-          keyPath: 'relPath'
-        });
-      }
+      db.createObjectStore(STORE_NAME, {
+        keyPath: 'relPath'
+      });
     };
 
     req.onsuccess = (event) => {
@@ -28,64 +33,116 @@ function openDb(storeName) {
   });
 }
 
-// delete store in database
-function deleteStore(storeName) {
+// delete cache backend database
+function deleteDb(repoId) {
+  return deleteDbByName(dbName(repoId));
+}
+
+// delete database by its name
+function deleteDbByName(name) {
   return new Promise((resolve, reject) => {
-    const ver = Math.floor(Date.now() / 1000);
-    let req = indexedDB.open(LOCAL_CACHE_DB, ver);
+    let req = indexedDB.deleteDatabase(name);
 
     req.onerror = (event) => {
       reject('IndexedDB is forbidden to use');
     };
 
-    req.onupgradeneeded = (event) => {
-      let db = event.target.result;
-      if (db.objectStoreNames.contains(storeName)) {
-        db.deleteObjectStore(storeName);
-      }
-      resolve();
-    };
-
     req.onsuccess = (event) => {
-      let db = event.target.result;
-      db.close();
+      resolve();
     };
   });
 }
 
-// local cache backend
-class CacheBackend {
-  constructor() {
+// cache meta item key, it is always kept in memory
+const META_KEY = 'cache_meta';
+
+// delay in ms before pending changes are written to local cache db
+const FLUSH_DELAY = 500;
+
+// number of items loaded between two progress reports when opening
+const PROGRESS_ITEMS = 64;
+
+// local cache backend, one instance for each opened repo
+//
+// Changes are written through to IndexedDB in batched transactions. Because
+// wasm reads cache synchronously, items cannot be loaded from IndexedDB on
+// demand, and ZboxFS assumes every item listed in its cache meta is readable.
+// So all items are loaded into memory when opening, memory usage is bounded
+// by the repo's cache size which ZboxFS enforces.
+//
+// ZboxFS evicts least recently used items itself when the repo's cache size
+// is exceeded, so no eviction is done here, capacity is only for statistics.
+export class CacheBackend {
+  constructor(repoId, capacity) {
     this.db = null;
-    this.storeName = null;
-    this.map= new Map();
+    this.repoId = repoId;
+    this.storeName = STORE_NAME;
+    this.capacity = capacity; // max total bytes of cached items
+    this.sizes = new Map();   // cached items size, excluding cache meta
+    this.used = 0;            // total bytes of cached items
+    this.hits = 0;
+    this.misses = 0;
+    this.map = new Map();     // all cached items
+    this.pending = new Map(); // pending changes, value is null for removal
+    this.flushTimer = null;
+    this.flushing = Promise.resolve();
     this.dbClosed = true;
   }
 
-  open(storeName) {
+  // open local cache db and load all items, onProgress(done, total) is called
+  // periodically with the number of items loaded
+  open(onProgress) {
     let self = this;
+    const report = onProgress || (() => {});
 
-    return openDb(storeName)
+    return openDb(self.repoId)
       .then(db => {
         self.db = db;
-        self.storeName = storeName;
 
         return new Promise((resolve, reject) => {
-          // load all items in local cache db into memory map
-          let req = self.db.transaction(storeName, 'readonly')
-            .objectStore(storeName)
-            .getAll();
+          let store = self.db.transaction(self.storeName, 'readonly')
+            .objectStore(self.storeName);
+
+          // load cache meta first, then other items
+          let req = store.get(META_KEY);
           req.onerror = (event) => {
             reject('Database error: ' + event.target.errorCode);
           };
           req.onsuccess = (event) => {
-            let items = event.target.result;
-            items.forEach(item => {
-              self.map.set(item.relPath, item.data);
-            });
-            logger.debug(`Cache backend opened: ${items.length} cache items loaded`);
-            self.dbClosed = false;
-            resolve();
+            let item = event.target.result;
+            if (item) self.map.set(item.relPath, item.data);
+
+            let total = 0;
+            let done = 0;
+            let countReq = store.count();
+            countReq.onsuccess = (event) => {
+              total = event.target.result;
+              report(done, total);
+            };
+
+            let cursorReq = store.openCursor();
+            cursorReq.onerror = (event) => {
+              reject('Database error: ' + event.target.errorCode);
+            };
+            cursorReq.onsuccess = (event) => {
+              let cursor = event.target.result;
+              if (cursor) {
+                const { relPath, data } = cursor.value;
+                if (relPath !== META_KEY) {
+                  self._track(relPath, data.byteLength);
+                  self.map.set(relPath, data);
+                }
+                if (++done % PROGRESS_ITEMS === 0) report(done, total);
+                cursor.continue();
+                return;
+              }
+              logger.debug(`Cache backend opened: ${self.sizes.size} cache ` +
+                `items loaded`);
+              report(done, total);
+
+              self.dbClosed = false;
+              resolve();
+            };
           };
         });
       });
@@ -94,9 +151,12 @@ class CacheBackend {
   // immediate close database withouth saving data
   immediateClose() {
     if (!this.dbClosed) {
+      clearTimeout(this.flushTimer);
       this.db.close();
-      this.storeName = null;
       this.map.clear();
+      this.sizes.clear();
+      this.pending.clear();
+      this.used = 0;
       this.dbClosed = true;
     }
   }
@@ -108,37 +168,105 @@ class CacheBackend {
       return Promise.resolve();
     }
 
-    return new Promise((resolve, reject) => {
-      let tx = self.db.transaction(self.storeName, 'readwrite');
-
-      tx.onabort = (event) => {
-        self.map.clear();
-        reject('Database tx aborted: ' + event.target.errorCode);
-      };
-      tx.onerror = (event) => {
-        self.map.clear();
-        reject('Database tx error: ' + event.target.errorCode);
-      };
-      tx.oncomplete = (event) => {
+    return self.flush()
+      .finally(() => {
+        self.db.close();
         self.map.clear();
+        self.sizes.clear();
+        self.used = 0;
         self.dbClosed = true;
-        resolve();
-      };
+      });
+  }
+
+  // write all pending changes to local cache db in one transaction
+  flush() {
+    let self = this;
 
-      let store = tx.objectStore(self.storeName);
+    clearTimeout(self.flushTimer);
+    self.flushTimer = null;
 
-      // clear all store then save all items from memory map
-      store.clear();
-      self.map.forEach((value, key) => {
-        store.put({ relPath: key, data: value });
-      });
+    if (self.dbClosed || self.pending.size === 0) {
+      return self.flushing;
+    }
 
-      self.db.close();
+    const changes = self.pending;
+    self.pending = new Map();
+
+    // transactions are chained so changes are applied in order
+    self.flushing = self.flushing
+      .catch(() => {})
+      .then(() => new Promise((resolve, reject) => {
+        let tx = self.db.transaction(self.storeName, 'readwrite');
+
+        tx.onabort = (event) => {
+          reject('Database tx aborted: ' + event.target.errorCode);
+        };
+        tx.onerror = (event) => {
+          reject('Database tx error: ' + event.target.errorCode);
+        };
+        tx.oncomplete = (event) => {
+          resolve();
+        };
+
+        let store = tx.objectStore(self.storeName);
+        changes.forEach((data, key) => {
+          if (data === null) {
+            store.delete(key);
+          } else {
+            store.put({ relPath: key, data });
+          }
+        });
+      }));
+
+    self.flushing.catch(err => {
+      logger.error(`Flush local cache failed: ${err}`);
     });
+
+    return self.flushing;
+  }
+
+  // schedule a delayed flush, so changes can be batched
+  _scheduleFlush() {
+    if (this.flushTimer === null) {
+      this.flushTimer = setTimeout(() => this.flush(), FLUSH_DELAY);
+    }
+  }
+
+  // track item size
+  _track(relPath, len) {
+    this._untrack(relPath);
+    this.sizes.set(relPath, len);
+    this.used += len;
+  }
+
+  // remove item size
+  _untrack(relPath) {
+    const len = this.sizes.get(relPath);
+    if (len !== undefined) {
+      this.used -= len;
+      this.sizes.delete(relPath);
+    }
+  }
+
+  // get cache statistics
+  stats() {
+    return {
+      items: this.sizes.size,
+      bytes: this.used,
+      capacity: this.capacity,
+      hits: this.hits,
+      misses: this.misses
+    };
   }
 
   contains(relPath) {
-    return this.map.has(relPath);
+    const ret = this.map.has(relPath);
+    if (ret) {
+      this.hits++;
+    } else {
+      this.misses++;
+    }
+    return ret;
   }
 
   get(relPath) {
@@ -147,49 +275,85 @@ class CacheBackend {
 
   insert(relPath, data) {
     this.map.set(relPath, data);
+    this.pending.set(relPath, data);
+    if (relPath !== META_KEY) {
+      this._track(relPath, data.byteLength);
+    }
+    this._scheduleFlush();
   }
 
   remove(relPath) {
     this.map.delete(relPath);
+    this._untrack(relPath);
+    this.pending.set(relPath, null);
+    this._scheduleFlush();
   }
 
   clear() {
-    this.map.clear();
+    let self = this;
 
-    let req = this.db.transaction(this.storeName, "readwrite")
-      .objectStore(this.storeName)
-      .clear();
-    req.onerror = (event) => {
-      logger.error('Clear local cache failed ' + event.target.errorCode);
-    };
+    clearTimeout(self.flushTimer);
+    self.flushTimer = null;
+    self.map.clear();
+    self.sizes.clear();
+    self.pending.clear();
+    self.used = 0;
+
+    // chain after pending flushes, so no stale items are written afterwards
+    self.flushing = self.flushing
+      .catch(() => {})
+      .then(() => new Promise(resolve => {
+        let req = self.db.transaction(self.storeName, "readwrite")
+          .objectStore(self.storeName)
+          .clear();
+        req.onerror = (event) => {
+          logger.error('Clear local cache failed ' + event.target.errorCode);
+          resolve();
+        };
+        req.onsuccess = (event) => {
+          resolve();
+        };
+      }));
   }
 
-  destroy(storeName) {
-    return deleteStore(storeName);
+  destroy() {
+    return deleteDb(this.repoId);
   }
 }
 
-export let cacheBackend = new CacheBackend();
+// delete the database shared by all repos in previous versions, local cache
+// is now stored in one database per repo
+export function deleteLegacyDb() {
+  return deleteDbByName(LOCAL_CACHE_DB);
+}
+
+// cache backend used by wasm, wasm cannot tell which repo it is working on,
+// so it must be switched to the repo's backend before any repo operation
+let current = null;
+
+export function useCacheBackend(backend) {
+  current = backend;
+}
 
 // --------------------------
 // export functions for wasm
 // --------------------------
 export function contains(relPath) {
-  return cacheBackend.contains(relPath);
+  return current ? current.contains(relPath) : false;
 }
 
 export function get(relPath) {
-  return cacheBackend.get(relPath);
+  return current ? current.get(relPath) : undefined;
 }
 
 export function insert(relPath, data) {
-  cacheBackend.insert(relPath, data.slice());
+  if (current) current.insert(relPath, data.slice());
 }
 
 export function remove(relPath) {
-  cacheBackend.remove(relPath);
+  if (current) current.remove(relPath);
 }
 
 export function clear() {
-  cacheBackend.clear();
+  if (current) current.clear();
 }
@@ -0,0 +1,37 @@
+// ZboxFS error
+//
+// kind: error kind name, such as 'NotFound', 'AlreadyExists', 'WrongPassword'
+// code: ZboxFS error code, 0 if it is not from ZboxFS
+// op: originating operation, such as 'repo.openFile'
+// path: originating path, if any
+export class ZboxError extends Error {
+  constructor(kind, message, detail) {
+    super(message);
+    const dtl = detail || {};
+    this.name = 'ZboxError';
+    this.kind = kind;
+    this.code = dtl.code || 0;
+    this.op = dtl.op;
+    this.path = dtl.path;
+  }
+}
+
+// convert error to plain object, so it can be posted to main thread
+export function errorToMsg(err, op, path) {
+  if (err instanceof Error) {
+    const isZboxErr = (err.name === 'ZboxError');
+    return {
+      kind: isZboxErr ? err.kind : err.name,
+      code: err.code || 0,
+      message: err.message,
+      op: err.op || op,
+      path: err.path || path
+    };
+  }
+  return { kind: 'Other', code: 0, message: `${err}`, op, path };
+}
+
+// convert plain object posted from worker back to error
+export function errorFromMsg(msg) {
+  return new ZboxError(msg.kind, msg.message, msg);
+}
@@ -1,18 +1,44 @@
 import MsgTypes from "./message";
 import { logger } from './logger';
-import { isObject, isNumber, isString, isArrayBufferView, str2ab } from "./utils";
+import { ZboxError, errorFromMsg } from './error';
+import {
+  isObject,
+  isNumber,
+  isBigInt,
+  isString,
+  isFunction,
+  isArrayBufferView,
+  str2ab
+} from "./utils";
 
 // global context
 const ctx = {
   resolver: null,
-  worker: null
+  worker: null,
+  repos: new Map(),   // opened repos, key: repo id, value: Repo
+  generation: 0,      // increased when worker is crashed or exited
+  retired: new Map(), // retired generations, key: generation, value: error
+  envParams: null,    // last initEnv parameters, used for restart
+  autoRestart: false
 };
 
+// check if params is an object only contains call options
+function isCallOpts(params) {
+  if (!isObject(params)) {
+    return false;
+  }
+  const keys = Object.keys(params);
+  return keys.length > 0 &&
+    keys.every(key => ['signal', 'timeout', 'onProgress'].includes(key));
+}
+
 function getParamsType(params) {
   if (isString(params)) {
     return 'string';
   } else if (isNumber(params)) {
     return 'number';
+  } else if (isBigInt(params)) {
+    return 'bigint';
   } else if (isArrayBufferView(params)) {
     return 'buffer';
   } else if (params instanceof ArrayBuffer) {
@@ -28,10 +54,54 @@ function getParamsType(params) {
 class Base {
   constructor(scope) {
     this.scope = scope;
+
+    // objects created in a crashed worker are invalid
+    this.generation = ctx.generation;
   }
 
   // bind message resolver and post message to worker
-  _bindMsg(msgType, object, params) {
+  //
+  // callOpts.signal: AbortSignal to cancel the call
+  // callOpts.timeout: time in ms before the call is cancelled
+  // callOpts.onProgress: callback receives { phase, done, total } events
+  //   while the call is running
+  //
+  // a call is only cancelled when worker is not busy in it, so a call runs
+  // in one step, such as copyDirAll, cannot be cancelled once it is started
+  //
+  // Note: the call is rejected with AbortError right away, but abort does not
+  // roll back work the worker has started or is about to do, for example, a
+  // timed out removeFile, rename or writeOnce may still take effect. Handles
+  // opened by an aborted call are closed in the worker.
+  _bindMsg(msgType, object, params, callOpts) {
+    if (this.scope !== 'zbox' && this.generation !== ctx.generation) {
+      const err = ctx.retired.get(this.generation);
+      return Promise.reject(new ZboxError(err.kind,
+        `Object is invalid because ${err.message}`));
+    }
+
+    // worker is gone, only initEnv can start it again
+    if (!ctx.worker && msgType !== 'initEnv') {
+      return Promise.reject(new ZboxError('Closed', 'ZboxFS has exited'));
+    }
+
+    // methods without parameter take call options as the first argument, so
+    // do methods with optional parameter if it has call options keys only
+    const arg = MsgTypes[this.scope][msgType].arg;
+    const optsFirst = (arg.length === 0 && isObject(params)) ||
+      (MsgTypes[this.scope][msgType].optional && isCallOpts(params));
+    if (optsFirst && callOpts === undefined) {
+      callOpts = params;
+      params = undefined;
+    }
+
+    const { signal, timeout, onProgress } = callOpts || {};
+    if (signal && signal.aborted) {
+      return Promise.reject(
+        new ZboxError('AbortError', 'Operation is aborted')
+      );
+    }
+
     const msg = {
       scope: this.scope,
       type: msgType,
@@ -39,16 +109,22 @@ class Base {
       params
     };
 
+    // ask worker to report progress only if someone is listening
+    if (isFunction(onProgress)) {
+      msg.progress = true;
+    }
+
     // check parameters type
     const paramsType = getParamsType(params);
-    const arg = MsgTypes[this.scope][msgType].arg;
     const argIsOptional = MsgTypes[this.scope][msgType].optional;
     const decl = arg.find(arg => {
       const argType = isObject(arg) ? 'object' : arg;
       return paramsType === argType;
     });
     if (decl === undefined && arg.length > 0 && !argIsOptional) {
-      return Promise.reject(new Error('Wrong argument'));
+      return Promise.reject(
+        new ZboxError('InvalidArgument', 'Wrong argument')
+      );
     }
 
     // check required keys if params is object
@@ -58,12 +134,19 @@ class Base {
         return required && !params.hasOwnProperty(key);
       });
       if (notMatched) {
-        return Promise.reject(new Error('Wrong argument'));
+        return Promise.reject(
+          new ZboxError('InvalidArgument', 'Wrong argument')
+        );
       }
     }
 
     // set up logger
     if (msgType === 'initEnv') {
+      if (!ctx.worker) {
+        startWorker();
+        window.addEventListener('pagehide', this._onPageHide);
+      }
+      ctx.envParams = msg.params;
       if (msg.params && msg.params.log) {
         logger.config(msg.params.log);
         // cannot send logger functions to worker, so delete it
@@ -73,7 +156,9 @@ class Base {
 
     // deal with array buffer transfer
     let transBuf = undefined;
-    if (msgType === 'read' || msgType === 'write' || msgType === 'writeOnce') {
+    if (msgType === 'read' || msgType === 'write' || msgType === 'writeAll' ||
+      msgType === 'writeOnce')
+    {
       let buf = params.buffer || params;
 
       if (paramsType === 'string') {
@@ -88,27 +173,231 @@ class Base {
 
       transBuf = [buf];
     }
+    if (msgType === 'writeAt' && paramsType === 'object') {
+      const data = params.data;
+      const dataType = getParamsType(data);
+      if (dataType !== 'string' && dataType !== 'buffer') {
+        return Promise.reject(
+          new ZboxError('InvalidArgument', 'Wrong argument')
+        );
+      }
+      const buf = (dataType === 'string') ? str2ab(data) : (data.buffer || data);
+
+      msg.params = {
+        offset: params.offset,
+        buf,
+        bufOffset: data.byteOffset || 0,
+        len: data.byteLength
+      };
 
-    const self = this;
+      transBuf = [buf];
+    }
 
     return new Promise((resolve, reject) => {
-      ctx.resolver.add(self.scope, msgType, resolve, reject);
-      ctx.worker.postMessage(msg, transBuf);
+      let timer = null;
+
+      const cleanup = () => {
+        clearTimeout(timer);
+        if (signal) signal.removeEventListener('abort', onAbort);
+      };
+
+      // reject immediately and tell worker to stop the call if it is still
+      // running
+      const cancel = (err) => {
+        cleanup();
+        if (ctx.resolver.cancel(msg.id)) {
+          ctx.worker.postMessage({ scope: 'cancel', id: msg.id });
+          reject(err);
+        }
+      };
+
+      const onAbort = () => {
+        cancel(new ZboxError('AbortError', 'Operation is aborted'));
+      };
+
+      msg.id = ctx.resolver.add(
+        result => {
+          cleanup();
+          resolve(result);
+        },
+        err => {
+          cleanup();
+          reject(err);
+        },
+        msg.progress ? onProgress : undefined
+      );
+
+      if (signal) signal.addEventListener('abort', onAbort);
+      if (timeout > 0) {
+        timer = setTimeout(() => {
+          cancel(new ZboxError('AbortError', 'Operation timed out'));
+        }, timeout);
+      }
+
+      try {
+        ctx.worker.postMessage(msg, transBuf);
+      } catch (err) {
+        // message cannot be sent, such as params is not cloneable
+        ctx.resolver.remove(msg.id);
+        cleanup();
+        reject(err);
+      }
     });
   }
 }
 
 class Repo extends Base {
-  constructor() {
+  constructor(rid) {
     super('repo');
+    this.rid = rid;
+    this.listeners = [];    // change event listeners
 
     // add methods based on message types
     Object.keys(MsgTypes[this.scope]).forEach(msgType => {
-      Repo.prototype[msgType] = this._bindMsg.bind(this, msgType, null);
+      this[msgType] = this._bindMsg.bind(this, msgType, this.rid);
+    });
+
+    // importBlob also accepts path and blob as two arguments
+    const importBlob = this.importBlob;
+    this.importBlob = (path, blob, callOpts) => {
+      if (isString(path)) {
+        return importBlob({ path, blob }, callOpts);
+      }
+      return importBlob(path, blob);
+    };
+
+    // walkDir also accepts path and options as two arguments
+    const walkDir = this.walkDir;
+    this.walkDir = (path, opts, callOpts) => {
+      return isString(path) ? walkDir(Object.assign({}, opts, { path }), callOpts) : walkDir(path, opts);
+    };
+
+    // restoreVersion also accepts path and version number as two arguments,
+    // note a restore may add two versions if current content is longer than
+    // the restored one, the truncated content and then the restored content
+    const restoreVersion = this.restoreVersion;
+    this.restoreVersion = (path, num, callOpts) => {
+      if (isString(path)) {
+        return restoreVersion({ path, num }, callOpts);
+      }
+      return restoreVersion(path, num);
+    };
+
+    // writeFile also accepts path and data as two arguments
+    const writeFile = this.writeFile;
+    this.writeFile = (path, data, callOpts) => {
+      if (isString(path)) {
+        return writeFile({ path, data }, callOpts);
+      }
+      return writeFile(path, data);
+    };
+
+    // batch also accepts operation list and options as two arguments
+    const batch = this.batch;
+    this.batch = (ops, opts, callOpts) => {
+      if (Array.isArray(ops)) {
+        return batch(Object.assign({}, opts, { ops }), callOpts);
+      }
+      return batch(ops, opts);
+    };
+  }
+
+  // add event listener, only 'change' event is supported
+  //
+  // change event: { path, op, version, to }
+  on(event, callback) {
+    if (event === 'change') {
+      this.listeners.push(callback);
+    }
+    return this;
+  }
+
+  // remove event listener
+  off(event, callback) {
+    if (event === 'change') {
+      this.listeners = this.listeners.filter(cb => cb !== callback);
+    }
+    return this;
+  }
+
+  // watch changes on path and its descendants, return a function to stop
+  // watching
+  watch(path, callback) {
+    const prefix = path.endsWith('/') ? path : path + '/';
+    const isWatched = (p) => p !== undefined &&
+      (p === path || p.startsWith(prefix));
+    const listener = (change) => {
+      if (isWatched(change.path) || isWatched(change.to)) {
+        callback(change);
+      }
+    };
+    this.on('change', listener);
+    return () => this.off('change', listener);
+  }
+
+  _emitChange(change) {
+    this.listeners.forEach(cb => {
+      try {
+        cb(change);
+      } catch (err) {
+        logger.error(`Change listener error: ${err}`);
+      }
     });
   }
 }
 
+// default chunk size for stream reading
+const STREAM_CHUNK_SIZE = 64 * 1024;
+
+// create a readable stream on file or version reader, each pull reads one
+// chunk from worker, so the stream consumer controls the pace
+function createReadable(rdr, opts) {
+  const chunkSize = (opts && opts.chunkSize) || STREAM_CHUNK_SIZE;
+
+  return new ReadableStream({
+    pull(controller) {
+      return rdr.read(new Uint8Array(chunkSize)).then(chunk => {
+        if (chunk.length > 0) {
+          controller.enqueue(chunk);
+        } else {
+          controller.close();
+        }
+      });
+    }
+  }, { highWaterMark: 1 });
+}
+
+// create a writable stream on file, each chunk is written to worker before
+// the next one is accepted, file is finished when stream is closed
+//
+// ZboxFS cannot abort a started write, so when stream is aborted the write is
+// finished to end it and then content before the stream is restored, this
+// leaves the partial content and the restored content in file history
+function createWritable(file) {
+  let startVer;
+  return new WritableStream({
+    start() {
+      return file.currVersion().then(ver => {
+        startVer = ver;
+      });
+    },
+
+    write(chunk) {
+      return file.writeAll(chunk);
+    },
+
+    close() {
+      return file.finish();
+    },
+
+    abort() {
+      // nothing to restore if nothing is written
+      return file.finish()
+        .then(() => file.restoreVersion(startVer), () => {});
+    }
+  }, { highWaterMark: 1 });
+}
+
 class File extends Base {
   constructor(fd) {
     super('file');
@@ -116,8 +405,32 @@ class File extends Base {
 
     // add methods based on message types
     Object.keys(MsgTypes[this.scope]).forEach(msgType => {
-      File.prototype[msgType] = this._bindMsg.bind(this, msgType, this.fd);
+      this[msgType] = this._bindMsg.bind(this, msgType, this.fd);
     });
+
+    // readAt and writeAt also accept positional arguments
+    const readAt = this.readAt;
+    this.readAt = (offset, len, callOpts) => {
+      if (!isObject(offset)) {
+        return readAt({ offset, len }, callOpts);
+      }
+      return readAt(offset, len);
+    };
+    const writeAt = this.writeAt;
+    this.writeAt = (offset, data, callOpts) => {
+      if (!isObject(offset)) {
+        return writeAt({ offset, data }, callOpts);
+      }
+      return writeAt(offset, data);
+    };
+  }
+
+  readable(opts) {
+    return createReadable(this, opts);
+  }
+
+  writable() {
+    return createWritable(this);
   }
 }
 
@@ -128,24 +441,113 @@ class VersionReader extends Base {
 
     // add methods based on message types
     Object.keys(MsgTypes[this.scope]).forEach(msgType => {
-      VersionReader.prototype[msgType] = this._bindMsg.bind(this, msgType, this.vrdr);
+      this[msgType] = this._bindMsg.bind(this, msgType, this.vrdr);
     });
+
+    // readAt also accepts positional arguments
+    const readAt = this.readAt;
+    this.readAt = (offset, len, callOpts) => {
+      if (!isObject(offset)) {
+        return readAt({ offset, len }, callOpts);
+      }
+      return readAt(offset, len);
+    };
+  }
+
+  readable(opts) {
+    return createReadable(this, opts);
   }
 }
 
 class Resolver {
   constructor() {
-    this.map = Object.keys(MsgTypes).reduce((accum, curr) => {
-      accum[curr] = Object.keys(MsgTypes[curr]).reduce((a, c) => {
-        a[c] = { resolve: null, reject: null };
-        return a;
-      }, {});
-      return accum;
-    }, {});
+    // pending calls
+    // key: request id
+    // value: { resolve, reject, onProgress }
+    this.map = new Map();
+    this.cancelled = new Set();   // ids of cancelled calls
+    this.seq = 0;
+  }
+
+  // add a pending call and return its unique request id
+  add(resolve, reject, onProgress) {
+    const id = ++this.seq;
+    this.map.set(id, { resolve, reject, onProgress });
+    return id;
   }
 
-  add(scope, msgType, resolve, reject) {
-    this.map[scope][msgType] = { resolve, reject };
+  // remove a pending call which is not sent to worker
+  remove(id) {
+    this.map.delete(id);
+  }
+
+  // cancel a pending call, its result will be ignored, return false if the
+  // call is not pending
+  cancel(id) {
+    if (!this.map.has(id)) {
+      return false;
+    }
+    this.map.delete(id);
+    this.cancelled.add(id);
+    return true;
+  }
+
+  // reject all pending calls
+  rejectAll(err) {
+    this.map.forEach(pending => pending.reject(err));
+    this.map.clear();
+    this.cancelled.clear();
+  }
+
+  // close the handle opened by a cancelled call, so the worker won't keep it
+  // forever, for repo this also releases its lock
+  closeCancelled(msg) {
+    if (msg.error || !ctx.worker) {
+      return;
+    }
+
+    const close = (scope, object) => {
+      const closeMsg = { scope, type: 'close', object };
+      closeMsg.id = this.add(() => {}, (err) => {
+        logger.warn(`Close cancelled ${scope} failed: ${err}`);
+      });
+      ctx.worker.postMessage(closeMsg);
+    };
+
+    const msgTypes = MsgTypes[msg.scope];
+    switch (msg.scope) {
+      case 'zbox':
+        if (msg.type === msgTypes.openRepo.name) {
+          close('repo', msg.result);
+        }
+        break;
+
+      case 'repo':
+        switch (msg.type) {
+          case msgTypes.openFile.name:
+          case msgTypes.createFile.name:
+            close('file', msg.result);
+            break;
+
+          case msgTypes.batch.name:
+            msg.result.forEach((ret, i) => {
+              const type = msg.params.ops[i].type;
+              if (!ret.error && (type === msgTypes.openFile.name ||
+                type === msgTypes.createFile.name))
+              {
+                close('file', ret.result);
+              }
+            });
+            break;
+        }
+        break;
+
+      case 'file':
+        if (msg.type === msgTypes.versionReader.name) {
+          close('versionReader', msg.result);
+        }
+        break;
+    }
   }
 
   resolve(event) {
@@ -178,9 +580,48 @@ class Resolver {
       return;
     }
 
+    // worker is crashed
+    if (msg.scope === 'crash') {
+      handleCrash(msg.error.message);
+      return;
+    }
+
+    // dispatch repo change event
+    if (msg.scope === 'event') {
+      const repo = ctx.repos.get(msg.object);
+      if (repo) repo._emitChange(msg.change);
+      return;
+    }
+
+    // intermediate progress of a running call
+    if (msg.scope === 'progress') {
+      const pending = this.map.get(msg.id);
+      if (pending && pending.onProgress) {
+        try {
+          pending.onProgress(msg.progress);
+        } catch (err) {
+          logger.error(`Progress callback failed: ${err}`);
+        }
+      }
+      return;
+    }
+
+    // ignore result of cancelled call
+    if (this.cancelled.delete(msg.id)) {
+      this.closeCancelled(msg);
+      return;
+    }
+
+    const pending = this.map.get(msg.id);
+    if (!pending) {
+      logger.warn(`No pending call for message ${msg.scope}.${msg.type}`);
+      return;
+    }
+    this.map.delete(msg.id);
+
     if (msg.error) {
-      const err = new Error(msg.error);
-      this.map[msg.scope][msg.type].reject(err);
+      const err = errorFromMsg(msg.error);
+      pending.reject(err);
       return;
     }
 
@@ -191,7 +632,8 @@ class Resolver {
       case 'zbox': {
         switch (msg.type) {
           case msgTypes.openRepo.name:
-            result = new Repo();
+            result = new Repo(result);
+            ctx.repos.set(result.rid, result);
             break;
         }
         break;
@@ -199,10 +641,28 @@ class Resolver {
 
       case 'repo': {
         switch (msg.type) {
+          case msgTypes.close.name:
+            ctx.repos.delete(msg.object);
+            break;
+
           case msgTypes.openFile.name:
           case msgTypes.createFile.name:
             result = new File(result);
             break;
+
+          case msgTypes.batch.name:
+            result = result.map((ret, i) => {
+              if (ret.error) {
+                return { error: errorFromMsg(ret.error) };
+              }
+              switch (msg.params.ops[i].type) {
+                case msgTypes.openFile.name:
+                case msgTypes.createFile.name:
+                  return { result: new File(ret.result) };
+              }
+              return ret;
+            });
+            break;
         }
         break;
       }
@@ -210,6 +670,7 @@ class Resolver {
       case 'file': {
         switch (msg.type) {
           case msgTypes.read.name:
+          case msgTypes.readAt.name:
             result = new Uint8Array(result.buf, result.offset, result.len);
             break;
 
@@ -227,6 +688,7 @@ class Resolver {
       case 'versionReader': {
         switch (msg.type) {
           case msgTypes.read.name:
+          case msgTypes.readAt.name:
             result = new Uint8Array(result.buf, result.offset, result.len);
             break;
 
@@ -238,7 +700,7 @@ class Resolver {
       }
     }
 
-    this.map[msg.scope][msg.type].resolve(result);
+    pending.resolve(result);
   }
 }
 
@@ -262,8 +724,62 @@ const wasmSupported = (() => {
   return false;
 })();
 
+// create and start worker
+function startWorker() {
+  ctx.worker = new Worker(workerPath, { name: 'ZboxWorker' });
+  ctx.worker.onmessage = ctx.resolver.resolve.bind(ctx.resolver);
+  ctx.worker.onerror = (event) => {
+    event.preventDefault();
+    handleCrash(event.message);
+  };
+}
+
+// invalidate all objects created in current worker, calls on them will fail
+// with error of the given kind
+function retireGeneration(kind, message) {
+  ctx.retired.set(ctx.generation, { kind, message });
+  ctx.generation++;
+}
+
+// reject all pending calls and invalidate all objects created in the crashed
+// worker, then restart worker if it is enabled
+function handleCrash(reason) {
+  if (!ctx.worker) {
+    return;
+  }
+
+  logger.error(`ZboxFS worker crashed: ${reason}`);
+
+  ctx.worker.terminate();
+  ctx.worker = null;
+  ctx.repos.clear();
+  retireGeneration('WorkerCrashed', 'ZboxFS worker was crashed');
+  ctx.resolver.rejectAll(
+    new ZboxError('WorkerCrashed', `ZboxFS worker crashed: ${reason}`)
+  );
+
+  if (ctx.autoRestart) {
+    logger.info('Restart ZboxFS worker');
+    startWorker();
+    if (ctx.envParams !== null) {
+      const msg = { scope: 'zbox', type: 'initEnv', params: ctx.envParams };
+      msg.id = ctx.resolver.add(() => {}, (err) => {
+        logger.error(`Restart ZboxFS worker failed: ${err}`);
+      });
+      ctx.worker.postMessage(msg);
+    }
+  }
+}
+
+// default timeout in ms when exiting
+const EXIT_TIMEOUT = 5000;
+
+export { ZboxError };
+
+// opts.autoRestart: restart worker and initialise environment again
+//                   automatically if worker crashed, default is false
 export class Zbox extends Base {
-  constructor() {
+  constructor(opts) {
     super('zbox');
 
     // check browser support
@@ -279,16 +795,48 @@ export class Zbox extends Base {
 
     // initialise global context objects
     ctx.resolver = new Resolver();
-    ctx.worker = new Worker(workerPath, { name: 'ZboxWorker' });
-    ctx.worker.onmessage = ctx.resolver.resolve.bind(ctx.resolver);
-    ctx.worker.onerror = (err) => {
-      logger.error(`ZboxFS worker error: ${JSON.stringify(err)}`);
-    };
+    ctx.autoRestart = !!(opts && opts.autoRestart);
+    startWorker();
 
     // add methods based on message types
     Object.keys(MsgTypes[this.scope]).forEach(msgType => {
-      Zbox.prototype[msgType] = this._bindMsg.bind(this, msgType, null);
+      this[msgType] = this._bindMsg.bind(this, msgType, null);
     });
+
+    // best-effort attempt to save everything when page is going away, page
+    // may be discarded before shutdown is completed
+    this._onPageHide = (event) => {
+      if (ctx.worker && !event.persisted) {
+        this.shutdown().catch(() => {});
+      }
+    };
+    window.addEventListener('pagehide', this._onPageHide);
+  }
+
+  // watch changes of a repo made in other browser contexts, without opening
+  // it, so it can be used while the repo is opened for writing elsewhere,
+  // return a function to stop watching
+  //
+  // change event: { path, op, version, to }
+  watchRepo(uri, callback) {
+    const search = /^zbox:\/\/\w+@(\w+)/.exec(uri);
+    if (!search) {
+      throw new ZboxError('InvalidUri', 'Invalid Uri');
+    }
+    if (typeof BroadcastChannel === 'undefined') {
+      throw new ZboxError('NotSupported',
+        "Your browser doesn't support BroadcastChannel");
+    }
+
+    const channel = new BroadcastChannel(`zbox_repo_${search[1]}`);
+    channel.onmessage = (event) => {
+      try {
+        callback(event.data);
+      } catch (err) {
+        logger.error(`Change listener error: ${err}`);
+      }
+    };
+    return () => channel.close();
   }
 
   static get SeekFrom() {
@@ -299,12 +847,78 @@ export class Zbox extends Base {
     };
   }
 
-  exit() {
-    if (ctx.worker) {
-      ctx.worker.terminate();
-      ctx.worker = null;
-      logger.info('ZboxFS exited');
+  static get OpsLimit() {
+    return {
+      Interactive: 0,
+      Moderate: 1,
+      Sensitive: 2
+    };
+  }
+
+  static get MemLimit() {
+    return {
+      Interactive: 0,
+      Moderate: 1,
+      Sensitive: 2
+    };
+  }
+
+  static get Cipher() {
+    return {
+      Xchacha: 0,
+      Aes: 1
+    };
+  }
+
+  // exit ZboxFS, opened files and repos are closed and local cache is saved
+  // before worker is terminated, pending calls are then rejected and all
+  // objects become invalid, call initEnv to start ZboxFS again
+  //
+  // Note: content written by file.write() but not finished yet is saved as a
+  // new version when exiting, unless the last write failed. Aborted writable
+  // stream has already ended its write and restored previous content.
+  //
+  // opts.force: terminate worker immediately without closing anything
+  // opts.timeout: max time in ms to wait for closing, default is 5 seconds
+  exit(opts) {
+    const { force = false, timeout = EXIT_TIMEOUT } = opts || {};
+
+    if (!ctx.worker) {
+      return Promise.resolve();
+    }
+
+    const terminate = () => {
+      if (ctx.worker) {
+        ctx.worker.terminate();
+        ctx.worker = null;
+        ctx.repos.clear();
+        retireGeneration('Closed', 'ZboxFS has exited');
+        ctx.resolver.rejectAll(new ZboxError('Closed', 'ZboxFS has exited'));
+        window.removeEventListener('pagehide', this._onPageHide);
+        logger.info('ZboxFS exited');
+      }
+    };
+
+    if (force) {
+      terminate();
+      return Promise.resolve();
     }
-    return Promise.resolve();
+
+    let timer;
+    const timeoutWait = new Promise(resolve => {
+      timer = setTimeout(() => {
+        logger.warn('ZboxFS shutdown timeout, force exit');
+        resolve();
+      }, timeout);
+    });
+    const shutdown = this.shutdown().catch(err => {
+      logger.error(`ZboxFS shutdown failed: ${err}`);
+    });
+
+    return Promise.race([shutdown, timeoutWait])
+      .then(() => {
+        clearTimeout(timer);
+        terminate();
+      });
   }
 }
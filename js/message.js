@@ -8,6 +8,10 @@ const MsgTypes = {
       arg: [{ debug: { type: 'boolean', optional: true } }],
       optional: true
     },
+    shutdown: {
+      name: 'shutdown',
+      arg: []
+    },
     version: {
       name: 'version',
       arg: []
@@ -46,12 +50,26 @@ const MsgTypes = {
       name: 'info',
       arg: []
     },
+    unlock: {
+      name: 'unlock',
+      arg: ['string']
+    },
+    cacheStats: {
+      name: 'cacheStats',
+      arg: []
+    },
+    flushCache: {
+      name: 'flushCache',
+      arg: []
+    },
     resetPassword: {
       name: 'resetPassword',
-      arg: [
-        { oldPwd: { type: 'string' } },
-        { newPwd: { type: 'string' } }
-      ]
+      arg: [{
+        oldPwd: { type: 'string' },
+        newPwd: { type: 'string' },
+        opsLimit: { type: 'number', optional: true },
+        memLimit: { type: 'number', optional: true }
+      }]
     },
     pathExists: {
       name: 'pathExists',
@@ -91,6 +109,18 @@ const MsgTypes = {
       name: 'readDir',
       arg: ['string']
     },
+    walkDir: {
+      name: 'walkDir',
+      arg: [
+        'string',
+        {
+          path: { type: 'string' },
+          maxDepth: { type: 'number', optional: true },
+          filter: { type: 'string', optional: true },
+          includeDirs: { type: 'boolean', optional: true }
+        }
+      ]
+    },
     metadata: {
       name: 'metadata',
       arg: ['string']
@@ -99,6 +129,13 @@ const MsgTypes = {
       name: 'history',
       arg: ['string']
     },
+    restoreVersion: {
+      name: 'restoreVersion',
+      arg: [{
+        path: { type: 'string' },
+        num: { type: 'number' }
+      }]
+    },
     copy: {
       name: 'copy',
       arg: [
@@ -131,6 +168,37 @@ const MsgTypes = {
         { from: { type: 'string' } },
         { to: { type: 'string' } }
       ]
+    },
+    importBlob: {
+      name: 'importBlob',
+      arg: [{
+        path: { type: 'string' },
+        blob: { type: 'object' }
+      }]
+    },
+    exportBlob: {
+      name: 'exportBlob',
+      arg: [
+        'string',
+        {
+          path: { type: 'string' },
+          type: { type: 'string', optional: true }
+        }
+      ]
+    },
+    writeFile: {
+      name: 'writeFile',
+      arg: [{
+        path: { type: 'string' },
+        data: { type: 'object' }
+      }]
+    },
+    batch: {
+      name: 'batch',
+      arg: [{
+        ops: { type: 'array' },
+        stopOnError: { type: 'boolean', optional: true }
+      }]
     }
   },
 
@@ -143,18 +211,30 @@ const MsgTypes = {
       name: 'read',
       arg: ['buffer']
     },
+    readAt: {
+      name: 'readAt',
+      arg: [{
+        offset: { type: 'number' },
+        len: { type: 'number' }
+      }]
+    },
     readAll: {
       name: 'readAll',
       arg: []
     },
     readAllString: {
       name: 'readAllString',
-      arg: []
+      arg: ['string'],
+      optional: true
     },
     write: {
       name: 'write',
       arg: ['string', 'buffer']
     },
+    writeAll: {
+      name: 'writeAll',
+      arg: ['string', 'buffer']
+    },
     finish: {
       name: 'finish',
       arg: []
@@ -163,6 +243,18 @@ const MsgTypes = {
       name: 'writeOnce',
       arg: ['string', 'buffer']
     },
+    writeAt: {
+      name: 'writeAt',
+      arg: [{
+        offset: { type: 'number' },
+        data: { type: 'buffer' }
+      }]
+    },
+    toBlob: {
+      name: 'toBlob',
+      arg: [{ type: { type: 'string', optional: true } }],
+      optional: true
+    },
     seek: {
       name: 'seek',
       arg: [ {
@@ -172,7 +264,7 @@ const MsgTypes = {
     },
     setLen: {
       name: 'setLen',
-      arg: ['number']
+      arg: ['number', 'bigint']
     },
     currVersion: {
       name: 'currVersion',
@@ -186,6 +278,10 @@ const MsgTypes = {
       name: 'history',
       arg: []
     },
+    restoreVersion: {
+      name: 'restoreVersion',
+      arg: ['number']
+    },
     versionReader: {
       name: 'versionReader',
       arg: ['number']
@@ -205,13 +301,21 @@ const MsgTypes = {
       name: 'read',
       arg: ['buffer']
     },
+    readAt: {
+      name: 'readAt',
+      arg: [{
+        offset: { type: 'number' },
+        len: { type: 'number' }
+      }]
+    },
     readAll: {
       name: 'readAll',
       arg: []
     },
     readAllString: {
       name: 'readAllString',
-      arg: []
+      arg: ['string'],
+      optional: true
     },
     seek: {
       name: 'seek',
@@ -1,3 +1,5 @@
+import { ZboxError } from './error';
+
 // check if it is object
 export function isObject(a) {
   return a !== null && typeof a === 'object';
@@ -13,6 +15,16 @@ export function isString(s) {
   return typeof s === 'string';
 }
 
+// check if it is BigInt
+export function isBigInt(n) {
+  return typeof n === 'bigint';
+}
+
+// check if it is a function
+export function isFunction(f) {
+  return typeof f === 'function';
+}
+
 // check if it is array buffer view
 export function isArrayBufferView(value) {
   return value
@@ -20,45 +32,79 @@ export function isArrayBufferView(value) {
     && value.byteLength !== undefined;
 }
 
-// convert ArrayBuffer to String
-export function ab2str(buf) {
-  return String.fromCharCode.apply(null, new Uint16Array(buf));
+// convert ArrayBuffer to String, content is decoded as UTF-8 by default
+export function ab2str(buf, encoding) {
+  let decoder;
+  try {
+    decoder = new TextDecoder(encoding || 'utf-8');
+  } catch (err) {
+    throw new ZboxError('InvalidArgument',
+      `Wrong argument, unsupported encoding '${encoding}'`);
+  }
+  return decoder.decode(buf);
 }
 
-// convert String to ArrayBuffer
+// convert String to ArrayBuffer, content is encoded as UTF-8
 export function str2ab(str) {
-  var buf = new ArrayBuffer(str.length * 2); // 2 bytes for each char
-  var bufView = new Uint16Array(buf);
-  for (var i = 0, strLen = str.length; i < strLen; i++) {
-    bufView[i] = str.charCodeAt(i);
-  }
-  return buf;
+  return new TextEncoder().encode(str).buffer;
 }
 
 // ensure arg is string
 export function ensureStr(s) {
   if (typeof s !== 'string') {
-    throw 'Wrong argument, string required';
+    throw new ZboxError('InvalidArgument',
+      'Wrong argument, string required');
   }
 }
 
 // ensure two args are string
 export function ensureStr2(s, s2) {
   if (typeof s !== 'string' || typeof s2 !== 'string') {
-    throw 'Wrong argument, string required';
+    throw new ZboxError('InvalidArgument',
+      'Wrong argument, string required');
   }
 }
 
 // ensure one parameter is positive integer number
 export function ensureInt(n) {
   if (!Number.isInteger(n) || n < 0) {
-    throw 'Wrong argument, positive integer required';
+    throw new ZboxError('InvalidArgument',
+      'Wrong argument, positive integer required');
+  }
+}
+
+// convert positive safe integer number or BigInt to 64-bit BigInt
+export function toUint64(n) {
+  const ret = toInt64(n);
+  if (ret < 0) {
+    throw new ZboxError('InvalidArgument',
+      'Wrong argument, positive integer required');
+  }
+  return ret;
+}
+
+// convert positive or negative safe integer number or BigInt to 64-bit BigInt
+export function toInt64(n) {
+  if (isBigInt(n)) {
+    if (BigInt.asIntN(64, n) !== n) {
+      throw new ZboxError('InvalidArgument',
+        'Wrong argument, integer exceeds 64 bits');
+    }
+    return n;
+  }
+  if (!Number.isSafeInteger(n)) {
+    throw new ZboxError('InvalidArgument',
+      'Wrong argument, safe integer or BigInt required');
   }
+  return BigInt(n);
 }
 
-// ensure one parameter is positive or negative integer number
-export function ensureIntOrNeg(n) {
-  if (!Number.isInteger(n)) {
-    throw 'Wrong argument, integer required';
+// convert 64-bit BigInt to number, fail if it cannot be exactly represented
+export function toSafeNumber(n) {
+  const ret = Number(n);
+  if (!Number.isSafeInteger(ret)) {
+    throw new ZboxError('Overflow',
+      'Integer overflow, value exceeds Number.MAX_SAFE_INTEGER');
   }
+  return ret;
 }
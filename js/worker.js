@@ -1,44 +1,632 @@
 import MsgTypes from "./message";
 import { logger } from './worker_logger';
-import { cacheBackend } from './cache_backend';
+import {
+  CacheBackend,
+  useCacheBackend,
+  deleteLegacyDb
+} from './cache_backend';
+import { ZboxError, errorToMsg } from './error';
 import {
   isString,
   isObject,
   ensureStr,
   ensureStr2,
   ensureInt,
-  ensureIntOrNeg,
-  ab2str
+  toUint64,
+  toInt64,
+  toSafeNumber,
+  isArrayBufferView,
+  ab2str,
+  str2ab
 } from "./utils";
 
-// global zbox and repo object
+// global zbox object
 let zbox = null;
-let repo = null;
 
 // opened objects
-// key: ptr integer number
+// key: object id integer number
 // value: opened object
 let opened = {
-  files: {},      // files
-  vrdrs: {}       // version readers
+  repos: {},      // repos, value: { rid, repo, cache, releaseLock, channel }
+  files: {},      // files, value: { file, owner, path, writeFailed }
+  vrdrs: {}       // version readers, value: { vrdr, owner }
 };
 
+// last id of opened objects, wasm object pointer is not used as id because
+// it can be reused by another object after the object is freed
+let lastObjId = 0;
+
+function nextObjId() {
+  return ++lastObjId;
+}
+
+// notify repo change to main thread and other browser contexts
+//
+// change: { path, op, version, to }
+//   op: 'create', 'write', 'remove' or 'rename'
+//   version: new file version number, only for 'create' and 'write' on file
+//   to: new path, only for 'rename'
+function emitChange(entry, change) {
+  postMessage({ scope: 'event', type: 'change', object: entry.rid, change });
+  if (entry.channel) {
+    entry.channel.postMessage(change);
+  }
+}
+
+// open change notification channel for repo, changes are broadcast to
+// watchers in other browser contexts
+//
+// Repo lock makes sure no other browser context can write to the repo at the
+// same time, so nothing is received from the channel.
+function openChangeChannel(repoId) {
+  if (typeof BroadcastChannel === 'undefined') {
+    return null;
+  }
+  return new BroadcastChannel(`zbox_repo_${repoId}`);
+}
+
+// count opened files or version readers belong to a repo
+function countOwned(objs, owner) {
+  return Object.values(objs).filter(obj => obj.owner === owner).length;
+}
+
+// parse storage scheme in uri
+function parseScheme(uri) {
+  const search = /^(\w+):\/\//.exec(uri);
+  if (!search) {
+    throw new ZboxError('InvalidUri', 'Invalid Uri');
+  }
+  return search[1];
+}
+
 // parse repo id in uri
 function parseRepoIdInUri(uri) {
   const search = /^zbox:\/\/\w+@(\w+)/.exec(uri);
   if (!search) {
-    throw 'Invalid Uri';
+    throw new ZboxError('InvalidUri', 'Invalid Uri');
   }
   const repoId = search[1];
   return repoId;
 }
 
+// acquire a lock on repo shared by all browser contexts, so the same repo
+// cannot be opened by two tabs at the same time, read-only repos can share
+// the lock
+//
+// timeout: ms to wait for the lock, fail immediately if it is 0
+//
+// Return a promise resolved with a function to release the lock
+function acquireRepoLock(repoId, readOnly, timeout) {
+  // Web Locks API is not supported, skip locking
+  if (!navigator.locks) {
+    return Promise.resolve(() => {});
+  }
+
+  const name = `zbox_repo_${repoId}`;
+  const opts = { mode: readOnly ? 'shared' : 'exclusive' };
+  let timer = null;
+
+  if (timeout > 0) {
+    const ctl = new AbortController();
+    opts.signal = ctl.signal;
+    timer = setTimeout(() => ctl.abort(), timeout);
+  } else {
+    opts.ifAvailable = true;
+  }
+
+  return new Promise((resolve, reject) => {
+    const lockedErr = new ZboxError('RepoOpened',
+      'Repo is opened by another browser tab or window');
+
+    navigator.locks.request(name, opts, lock => {
+      clearTimeout(timer);
+      if (lock === null) {
+        reject(lockedErr);
+        return;
+      }
+
+      // hold the lock until it is released
+      return new Promise(release => resolve(release));
+    })
+    .catch(err => {
+      clearTimeout(timer);
+      reject(err.name === 'AbortError' ? lockedErr : err);
+    });
+  });
+}
+
+// run password key derivation, translate wasm memory failure to a readable
+// error because high memLimit may exceed what browser can allocate
+//
+// A wasm trap is kept as the error cause, so the worker is still treated as
+// crashed after the error is replied.
+function deriveKey(fn) {
+  try {
+    return fn();
+  } catch (err) {
+    if (err instanceof RangeError || err instanceof WebAssembly.RuntimeError) {
+      let oom = new ZboxError('OutOfMemory', 'Out of memory when deriving ' +
+        'key from password, try a lower memLimit');
+      oom.cause = err;
+      throw oom;
+    }
+    throw err;
+  }
+}
+
 // parse cache type in uri
 function parseCacheType(uri) {
   const search = /^zbox:\/\/\w+@\w+\?.*cache_type=(\w+).*/.exec(uri);
   return search ? search[1] : undefined;
 }
 
+// chunk size used when transferring blob content
+const BLOB_CHUNK_SIZE = 64 * 1024;
+
+// ids of running asynchronous calls and the ones cancelled by main thread
+const running = new Set();
+const cancelled = new Set();
+
+// size of data processed between two cancellation checks
+const CHECKPOINT_SIZE = 1024 * 1024;
+
+// give worker a chance to receive cancel message and throw if the call is
+// cancelled, cache backend is switched back because other calls may run in
+// between
+function checkpoint(msg, cache) {
+  return new Promise(resolve => setTimeout(resolve, 0))
+    .then(() => {
+      if (cancelled.has(msg.id)) {
+        throw new ZboxError('AbortError', 'Operation is aborted');
+      }
+      useCacheBackend(cache);
+    });
+}
+
+// post intermediate progress event to main thread, only if the caller has
+// asked for it
+function reportProgress(msg, phase, done, total) {
+  if (msg.progress) {
+    postMessage({
+      scope: 'progress',
+      id: msg.id,
+      progress: { phase, done, total }
+    });
+  }
+}
+
+// get progress callback for wasm, which reports (done, total) entries
+function progressCallback(msg, phase) {
+  if (!msg.progress) return undefined;
+  return (done, total) => reportProgress(msg, phase, done, total);
+}
+
+// get length from current position to end of file or version reader, the
+// position is not changed
+function remainingLen(rdr) {
+  const pos = rdr.seek(2, toInt64(0));
+  const end = rdr.seek(1, toInt64(0));
+  rdr.seek(0, pos);
+  return toSafeNumber(end - pos);
+}
+
+// run chunked operation with cancellation check between chunks, step()
+// processes one chunk and returns its length, 0 means operation is done,
+// progress of the phase is reported at each check and when it is done
+async function runChunked(msg, cache, step, phase, total) {
+  let processed = 0;
+  let done = 0;
+  reportProgress(msg, phase, done, total);
+  while (true) {
+    const len = step();
+    if (len === 0) break;
+    processed += len;
+    done += len;
+    if (processed >= CHECKPOINT_SIZE) {
+      processed = 0;
+      reportProgress(msg, phase, done, total);
+      await checkpoint(msg, cache);
+    }
+  }
+  reportProgress(msg, phase, done, total);
+}
+
+// run chunked write, cancellation is only checked before the first chunk
+// because zbox keeps a transaction open in this thread from the first write
+// until finish, and it cannot be aborted, so the write runs to the end without
+// yielding once it is started and progress is reported along the way
+async function runChunkedWrite(msg, cache, step, total) {
+  await checkpoint(msg, cache);
+
+  let processed = 0;
+  let done = 0;
+  reportProgress(msg, 'write', done, total);
+  while (true) {
+    const len = step();
+    if (len === 0) break;
+    processed += len;
+    done += len;
+    if (processed >= CHECKPOINT_SIZE) {
+      processed = 0;
+      reportProgress(msg, 'write', done, total);
+    }
+  }
+  reportProgress(msg, 'write', done, total);
+}
+
+// send message back to main thread when asynchronous operation is done, repo
+// entry which owns the call is not auto locked while the call is running
+function replyAsync(msg, promise, owner) {
+  running.add(msg.id);
+  if (owner) owner.running++;
+  promise
+    .then(result => {
+      msg.result = result;
+    })
+    .catch(err => {
+      logger.error(err);
+      setMsgError(msg, err);
+      return err;
+    })
+    .then(err => {
+      running.delete(msg.id);
+      cancelled.delete(msg.id);
+      if (owner) {
+        owner.running--;
+        touchRepo(owner);
+      }
+      const transBuf = (msg.result instanceof ArrayBuffer) ? [msg.result] : [];
+      postMessage(msg, transBuf);
+      checkCrash(err);
+    });
+}
+
+// cut off existing content beyond the new content, because written content is
+// merged with existing content instead of replacing it, file is truncated
+// afterwards so its content is kept if writing failed
+function truncateRest(file, len) {
+  if (file.metadata().contentLen > len) {
+    file.setLen(toUint64(len));
+  }
+}
+
+// write blob content to file in chunks, blob is read synchronously because
+// we are in worker
+async function writeBlob(file, blob, msg, cache) {
+  const rdr = new FileReaderSync();
+  let pos = 0;
+  await runChunkedWrite(msg, cache, () => {
+    if (pos >= blob.size) return 0;
+    const chunk = blob.slice(pos, pos + BLOB_CHUNK_SIZE);
+    const buf = new Uint8Array(rdr.readAsArrayBuffer(chunk));
+    let written = 0;
+    while (written < buf.length) {
+      written += file.write(buf.subarray(written));
+    }
+    pos += buf.length;
+    return buf.length;
+  }, blob.size);
+
+  // nothing to finish for empty blob
+  if (pos > 0) file.finish();
+  truncateRest(file, blob.size);
+}
+
+// convert string, ArrayBuffer or its view to Uint8Array, string is encoded as
+// UTF-8
+function toBytes(data) {
+  if (isString(data)) {
+    return new Uint8Array(str2ab(data));
+  } else if (data instanceof ArrayBuffer) {
+    return new Uint8Array(data);
+  } else if (isArrayBufferView(data)) {
+    return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+  }
+  throw new ZboxError('InvalidArgument',
+    'Wrong argument, string or buffer required');
+}
+
+// write buffer to file in chunks and finish it, so writing a large buffer can
+// report progress
+async function writeBuffer(file, buf, msg, cache) {
+  let pos = 0;
+  await runChunkedWrite(msg, cache, () => {
+    if (pos >= buf.length) return 0;
+    const written = file.write(buf.subarray(pos, pos + BLOB_CHUNK_SIZE));
+    pos += written;
+    return written;
+  }, buf.length);
+  file.finish();
+}
+
+// read file content from current position to blob in chunks, so the whole
+// content never needs to be in one buffer
+async function readBlob(file, type, msg, cache) {
+  const buf = new Uint8Array(BLOB_CHUNK_SIZE);
+  let parts = [];
+  const total = msg.progress ? remainingLen(file) : undefined;
+  await runChunked(msg, cache, () => {
+    const read = file.read(buf);
+    if (read > 0) {
+      parts.push(buf.slice(0, read));
+    }
+    return read;
+  }, 'read', total);
+  return new Blob(parts, { type });
+}
+
+// read all content from current position of file or version reader in
+// chunks, so it can be cancelled
+async function readAll(rdr, msg, cache) {
+  let chunks = [];
+  let len = 0;
+  const total = msg.progress ? remainingLen(rdr) : undefined;
+  await runChunked(msg, cache, () => {
+    const buf = new Uint8Array(BLOB_CHUNK_SIZE);
+    const read = rdr.read(buf);
+    if (read > 0) {
+      chunks.push(buf.subarray(0, read));
+      len += read;
+    }
+    return read;
+  }, 'read', total);
+
+  let dst = new Uint8Array(len);
+  let pos = 0;
+  chunks.forEach(chunk => {
+    dst.set(chunk, pos);
+    pos += chunk.length;
+  });
+  return dst;
+}
+
+// ensure arg is blob
+function ensureBlob(blob) {
+  if (!(blob instanceof Blob)) {
+    throw new ZboxError('InvalidArgument', 'Wrong argument, Blob required');
+  }
+}
+
+// default local cache size in MB, same as ZboxFS default
+const DEFAULT_CACHE_SIZE = 1;
+
+// parse cache size in MB in uri
+function parseCacheSize(uri) {
+  const search = /[?&]cache_size=(\d+)mb/i.exec(uri);
+  return search ? Number(search[1]) : DEFAULT_CACHE_SIZE;
+}
+
+// add cache size in MB to uri, so ZboxFS and cache backend use same budget
+function setCacheSize(uri, cacheSize) {
+  if (cacheSize === undefined) {
+    return uri;
+  }
+  ensureInt(cacheSize);
+  if (cacheSize < 1) {
+    throw new ZboxError('InvalidArgument', 'Cache size must be at least 1MB');
+  }
+  if (/[?&]cache_size=/.test(uri)) {
+    throw new ZboxError('InvalidArgument',
+      'Cache size is specified in both uri and options');
+  }
+  const sep = uri.includes('?') ? '&' : '?';
+  return `${uri}${sep}cache_size=${cacheSize}mb`;
+}
+
+// get the path which message is working on
+function getMsgPath(msg) {
+  // unlock message parameter is password, not path
+  if (msg.scope !== 'repo' || msg.type === 'unlock') {
+    return undefined;
+  }
+  if (isString(msg.params)) {
+    return msg.params;
+  }
+  if (isObject(msg.params)) {
+    return msg.params.path || msg.params.from;
+  }
+  return undefined;
+}
+
+// wasm trap leaves its memory in unknown state, so nothing can be run
+// safely afterwards, tell main thread the worker is crashed and close it
+function checkCrash(err) {
+  const trap = (err && err.cause instanceof WebAssembly.RuntimeError) ?
+    err.cause : err;
+  if (trap instanceof WebAssembly.RuntimeError) {
+    postMessage({ scope: 'crash', error: errorToMsg(trap) });
+    close();
+  }
+}
+
+// set error to message, with originating operation and path
+function setMsgError(msg, err) {
+  msg.error = errorToMsg(err, `${msg.scope}.${msg.type}`, getMsgPath(msg));
+}
+
+// find opened repo entry which uses the cache backend
+function findRepoByCache(cache) {
+  return Object.values(opened.repos).find(entry => entry.cache === cache);
+}
+
+// run fn(cache) for a repo which is not opened by its handle, such as
+// exists, destroy and repairSuperBlock, with the local cache backend of the
+// repo in uri, so other repos' cache is never touched
+//
+// Cache backend of the repo is reused if it is already opened, otherwise a
+// temporary one is opened and closed afterwards.
+function withRepoCache(uri, fn) {
+  const isZbox = parseScheme(uri) === 'zbox';
+  if (!isZbox || parseCacheType(uri) === 'mem') {
+    useCacheBackend(null);
+    return Promise.resolve().then(() => fn(null));
+  }
+
+  const repoId = parseRepoIdInUri(uri);
+  const entry = Object.values(opened.repos)
+    .find(entry => entry.cache && entry.cache.repoId === repoId);
+  if (entry) {
+    return Promise.resolve().then(() => {
+      useCacheBackend(entry.cache);
+      return fn(entry.cache);
+    });
+  }
+
+  const cache = new CacheBackend(repoId, parseCacheSize(uri) * 1024 * 1024);
+  let result;
+  return cache.open()
+    .then(() => {
+      useCacheBackend(cache);
+      result = fn(cache);
+    })
+    .finally(() => {
+      useCacheBackend(null);
+      return cache.close();
+    })
+    .then(() => result);
+}
+
+// create repo opener and open repo
+function openZbox(uri, pwd, opts, cache) {
+  let opener = new zbox.RepoOpener();
+
+  if (opts.hasOwnProperty('opsLimit')) {
+    ensureInt(opts.opsLimit);
+    opener.opsLimit(opts.opsLimit);
+  }
+  if (opts.hasOwnProperty('memLimit')) {
+    ensureInt(opts.memLimit);
+    opener.memLimit(opts.memLimit);
+  }
+  if (opts.hasOwnProperty('cipher')) {
+    ensureInt(opts.cipher);
+    opener.cipher(opts.cipher);
+  }
+  if (opts.hasOwnProperty('create'))
+    opener.create(opts.create);
+  if (opts.hasOwnProperty('createNew'))
+    opener.createNew(opts.createNew);
+  if (opts.hasOwnProperty('compress'))
+    opener.compress(opts.compress);
+  if (opts.hasOwnProperty('versionLimit'))
+    opener.versionLimit(opts.versionLimit);
+  if (opts.hasOwnProperty('dedupChunk'))
+    opener.dedupChunk(opts.dedupChunk);
+  if (opts.hasOwnProperty('readOnly'))
+    opener.readOnly(opts.readOnly);
+  if (opts.hasOwnProperty('force'))
+    opener.force(opts.force);
+
+  useCacheBackend(cache);
+  return deriveKey(() => opener.open(uri, pwd));
+}
+
+// restart auto lock timer of repo, unless it is already locked or closed
+function touchRepo(entry) {
+  if (entry.autoLockAfter && entry.repo && opened.repos[entry.rid] === entry) {
+    clearTimeout(entry.lockTimer);
+    entry.lockTimer = setTimeout(() => lockRepo(entry), entry.autoLockAfter);
+  }
+}
+
+// lock repo by closing it, so its key material is wiped from memory, all
+// files and version readers opened in it are dropped as well
+function lockRepo(entry) {
+  clearTimeout(entry.lockTimer);
+
+  // timer is restarted when the running calls are done
+  if (entry.running > 0) {
+    return;
+  }
+
+  Object.values(opened.files)
+    .filter(obj => obj.owner === entry && obj.file)
+    .forEach(obj => {
+      obj.file.close();
+      obj.file.free();
+      obj.file = null;
+    });
+  Object.values(opened.vrdrs)
+    .filter(obj => obj.owner === entry && obj.vrdr)
+    .forEach(obj => {
+      obj.vrdr.close();
+      obj.vrdr.free();
+      obj.vrdr = null;
+    });
+
+  useCacheBackend(entry.cache);
+  entry.repo.close();
+  entry.repo.free();
+  entry.repo = null;
+  if (entry.cache) {
+    entry.cache.flush()
+      .catch(err => logger.error(`flush local cache failed: ${err}`));
+  }
+
+  logger.info('Repo is locked after inactivity');
+}
+
+// close repo and save its local cache, then release repo lock
+function closeRepo(entry) {
+  clearTimeout(entry.lockTimer);
+  useCacheBackend(entry.cache);
+  if (entry.repo) entry.repo.close();
+  if (entry.channel) entry.channel.close();
+  delete opened.repos[entry.rid];
+
+  const closeCache = entry.cache ? entry.cache.close() : Promise.resolve();
+  return closeCache
+    .catch(err => {
+      logger.error(`close local cache failed: ${err}`);
+      throw err;
+    })
+    .finally(() => {
+      // release repo lock after cache is saved
+      entry.releaseLock();
+    });
+}
+
+// finish and close all opened files and version readers, then close all
+// repos, so nothing is lost before worker is terminated
+//
+// Content written by write() but not finished yet is saved as a new version,
+// unless its last write failed, in which case the write is abandoned.
+function shutdown() {
+  Object.keys(opened.files).forEach(key => {
+    const { file, owner, writeFailed } = opened.files[key];
+    if (file) {
+      useCacheBackend(owner.cache);
+      if (!writeFailed) {
+        try {
+          file.finish();
+        } catch (err) {
+          // file has nothing to finish
+        }
+      }
+      file.close();
+    }
+    delete opened.files[key];
+  });
+
+  Object.keys(opened.vrdrs).forEach(key => {
+    const { vrdr } = opened.vrdrs[key];
+    if (vrdr) vrdr.close();
+    delete opened.vrdrs[key];
+  });
+
+  const closeRepos = Object.values(opened.repos).map(entry => {
+    return closeRepo(entry).catch(() => {});
+  });
+  return Promise.all(closeRepos);
+}
+
+// ensure repo is not locked
+function ensureUnlocked(entry) {
+  if (entry.repo === null) {
+    throw new ZboxError('Locked', 'Repo is locked');
+  }
+}
+
 function zboxMsgHandler(msg, msgTypes) {
   switch (msg.type) {
     case msgTypes.initEnv.name: {
@@ -49,15 +637,24 @@ function zboxMsgHandler(msg, msgTypes) {
         .then(wasm => {
           zbox = wasm;
           zbox.init_env(level);
+          deleteLegacyDb()
+            .catch(err => logger.warn(`delete legacy local cache failed: ${err}`));
         })
         .catch(err => {
           logger.error(`load ZboxFS wasm failed: ${err}`);
-          msg.error = `${err}`;
+          setMsgError(msg, err);
         })
         .finally(() => postMessage(msg));
       return;
     }
 
+    case msgTypes.shutdown.name: {
+      shutdown()
+        .then(() => logger.info('ZboxFS worker is shut down'))
+        .finally(() => postMessage(msg));
+      return;
+    }
+
     case msgTypes.version.name: {
       msg.result = zbox.zbox_version();
       postMessage(msg);
@@ -66,95 +663,219 @@ function zboxMsgHandler(msg, msgTypes) {
 
     case msgTypes.exists.name: {
       ensureStr(msg.params);
-      msg.result = zbox.Repo.exists(msg.params);
-      postMessage(msg);
+      replyAsync(msg, withRepoCache(msg.params, () => {
+        return zbox.Repo.exists(msg.params);
+      }));
       return;
     }
 
     case msgTypes.destroy.name: {
       ensureStr(msg.params);
-      msg.result = zbox.Repo.destroy(msg.params);
-      postMessage(msg);
+      const uri = msg.params;
+      const destroy = withRepoCache(uri, cache => {
+        zbox.Repo.destroy(uri);
+        return cache;
+      })
+        .then(cache => {
+          // remove local cache database of the destroyed repo, unless the
+          // repo is still opened in this worker
+          if (cache && !findRepoByCache(cache)) return cache.destroy();
+        })
+        .then(() => null);
+      replyAsync(msg, destroy);
       return;
     }
 
     case msgTypes.openRepo.name: {
       ensureStr2(msg.params.uri, msg.params.pwd);
 
-      // find repo id in uri
-      const repoId = parseRepoIdInUri(msg.params.uri);
-
-      // load local cache backend and then open repo
-      let loadCache;
-      if (parseCacheType(msg.params.uri) === 'mem') {
-        // no need to load cache for memory backend
-        loadCache = Promise.resolve();
-      } else {
-        loadCache = cacheBackend.open(repoId);
+      const opts = msg.params.opts || {};
+      let uri = msg.params.uri;
+      let cache = null;
+      let acquireLock = Promise.resolve(() => {});
+      let releaseLock = null;
+      let repoId = null;
+
+      if (opts.hasOwnProperty('lockTimeout')) ensureInt(opts.lockTimeout);
+      if (opts.hasOwnProperty('autoLockAfter')) ensureInt(opts.autoLockAfter);
+
+      // route uri by storage scheme
+      switch (parseScheme(uri)) {
+        case 'zbox': {
+          // find repo id in uri
+          repoId = parseRepoIdInUri(uri);
+          uri = setCacheSize(uri, opts.cacheSize);
+          acquireLock = acquireRepoLock(repoId, !!opts.readOnly,
+            opts.lockTimeout || 0);
+
+          // load local cache backend, no need to load cache for memory
+          // cache type
+          if (parseCacheType(uri) !== 'mem') {
+            cache = new CacheBackend(repoId, parseCacheSize(uri) * 1024 * 1024);
+          }
+          break;
+        }
+
+        case 'mem': {
+          // in-memory repo has no local cache
+          if (opts.cacheSize !== undefined) {
+            throw new ZboxError('InvalidArgument',
+              'Cache size is not supported by memory repo');
+          }
+          break;
+        }
+
+        default:
+          throw new ZboxError('InvalidUri', 'Unsupported Uri scheme');
       }
-      loadCache
+
+      // lock repo and load cache backend, then open repo
+      acquireLock
+        .then(release => {
+          releaseLock = release;
+          return cache ? cache.open(progressCallback(msg, 'loadCache'))
+            : undefined;
+        })
         .then(() => {
-          // create and config opener
-          let opener = new zbox.RepoOpener();
-          let opts = msg.params.opts || {};
-
-          if (opts.hasOwnProperty('create'))
-            opener.create(opts.create);
-          if (opts.hasOwnProperty('createNew'))
-            opener.createNew(opts.createNew);
-          if (opts.hasOwnProperty('compress'))
-            opener.compress(opts.compress);
-          if (opts.hasOwnProperty('versionLimit'))
-            opener.versionLimit(opts.versionLimit);
-          if (opts.hasOwnProperty('dedupChunk'))
-            opener.dedupChunk(opts.dedupChunk);
-          if (opts.hasOwnProperty('readOnly'))
-            opener.readOnly(opts.readOnly);
-          if (opts.hasOwnProperty('force'))
-            opener.force(opts.force);
-
-          // open zbox
-          repo = opener.open(msg.params.uri, msg.params.pwd);
+          reportProgress(msg, 'deriveKey', 0, 1);
+          const repo = openZbox(uri, msg.params.pwd, opts, cache);
+          reportProgress(msg, 'deriveKey', 1, 1);
+          const channel = repoId ? openChangeChannel(repoId) : null;
+          const entry = {
+            rid: nextObjId(),
+            repo,
+            cache,
+            releaseLock,
+            channel,
+            uri,
+            opts,
+            autoLockAfter: opts.autoLockAfter || 0,
+            lockTimer: null,
+            running: 0      // number of running asynchronous calls
+          };
+          opened.repos[entry.rid] = entry;
+          touchRepo(entry);
+          msg.result = entry.rid;
         })
         .catch(err => {
           logger.error(`open repo failed: ${err}`);
-          msg.error = `${err}`;
-          cacheBackend.immediateClose();
+          setMsgError(msg, err);
+          if (cache) cache.immediateClose();
+          if (releaseLock) releaseLock();
+          return err;
         })
-        .finally(() => postMessage(msg));
+        .then(err => {
+          postMessage(msg);
+          checkCrash(err);
+        });
 
       return;
     }
 
     case msgTypes.repairSuperBlock.name: {
       ensureStr2(msg.params.uri, msg.params.pwd);
-      zbox.Repo.repairSuperBlock(msg.params.uri,
-          msg.params.pwd);
-      postMessage(msg);
+      replyAsync(msg, withRepoCache(msg.params.uri, () => {
+        zbox.Repo.repairSuperBlock(msg.params.uri, msg.params.pwd);
+        return null;
+      }));
       return;
     }
   }
 }
 
 function repoMsgHandler(msg, msgTypes) {
+  const entry = opened.repos[msg.object];
+
+  if (entry === undefined) {
+    // it is OK to close a closed repo
+    if (msg.type === msgTypes.close.name) {
+      postMessage(msg);
+      return;
+    }
+    throw new ZboxError('RepoClosed', 'Repo is closed');
+  }
+
+  useCacheBackend(entry.cache);
+
+  // locked repo only accepts close and unlock
+  if (msg.type !== msgTypes.close.name && msg.type !== msgTypes.unlock.name) {
+    ensureUnlocked(entry);
+    touchRepo(entry);
+  }
+
+  // send message back to main thread, unless it is replied asynchronously
+  if (!execRepoMsg(entry, msg, msgTypes)) {
+    postMessage(msg);
+  }
+}
+
+// repo messages which cannot be included in a batch, they either change repo
+// state or are replied asynchronously
+const NON_BATCH_MSGS = [
+  'close', 'unlock', 'flushCache', 'importBlob', 'exportBlob', 'batch'
+];
+
+// run repo operations in batch sequentially, result of each operation is
+// either { result } or { error }
+function runBatch(entry, msg, msgTypes) {
+  const { ops, stopOnError = false } = msg.params;
+  if (!Array.isArray(ops)) {
+    throw new ZboxError('InvalidArgument', 'Wrong argument, Array required');
+  }
+
+  let results = [];
+  for (let i = 0; i < ops.length; i++) {
+    const op = ops[i] || {};
+    let sub = {
+      scope: msg.scope,
+      type: op.type,
+      object: msg.object,
+      params: op.params,
+      result: null,
+      error: null
+    };
+
+    try {
+      const supported = msgTypes.hasOwnProperty(sub.type) &&
+        !NON_BATCH_MSGS.includes(sub.type);
+      if (!supported) {
+        throw new ZboxError('InvalidArgument',
+          `Operation ${sub.type} is not supported in batch`);
+      }
+      execRepoMsg(entry, sub, msgTypes);
+      results.push({ result: sub.result });
+    } catch (err) {
+      // wasm crash fails the whole batch
+      if (err instanceof WebAssembly.RuntimeError) throw err;
+      setMsgError(sub, err);
+      results.push({ error: sub.error });
+    }
+    reportProgress(msg, 'batch', i + 1, ops.length);
+
+    if (sub.error && stopOnError) break;
+  }
+  return results;
+}
+
+// execute repo message and set its result, return true if the message will
+// be replied asynchronously
+function execRepoMsg(entry, msg, msgTypes) {
+  const repo = entry.repo;
+
   switch (msg.type) {
     case msgTypes.close.name: {
-      let cnt = Object.keys(opened.files).length;
+      let cnt = countOwned(opened.files, entry);
       if (cnt > 0) {
         logger.warn(`${cnt} file(s) still opened when close repo`);
       }
-      cnt = Object.keys(opened.vrdrs).length;
+      cnt = countOwned(opened.vrdrs, entry);
       if (cnt > 0) {
         logger.warn(`${cnt} version reader(s) still opened when close repo`);
       }
-      repo.close();
-      cacheBackend.close()
-        .catch(err => {
-          logger.error(`close local cache failed: ${err}`);
-          msg.error = `${err}`;
-        })
+      closeRepo(entry)
+        .catch(err => setMsgError(msg, err))
         .finally(() => postMessage(msg));
-      return;
+      return true;
     }
 
     case msgTypes.info.name: {
@@ -162,9 +883,44 @@ function repoMsgHandler(msg, msgTypes) {
       break;
     }
 
+    case msgTypes.unlock.name: {
+      ensureStr(msg.params);
+      if (entry.repo === null) {
+        // repo must already exist when unlocking
+        const opts = Object.assign({}, entry.opts, {
+          create: false,
+          createNew: false
+        });
+        entry.repo = openZbox(entry.uri, msg.params, opts, entry.cache);
+        logger.info('Repo is unlocked');
+      }
+      touchRepo(entry);
+      break;
+    }
+
+    case msgTypes.cacheStats.name: {
+      // memory cache has no statistics
+      msg.result = entry.cache ? entry.cache.stats() : null;
+      break;
+    }
+
+    case msgTypes.flushCache.name: {
+      const flush = entry.cache ? entry.cache.flush() : Promise.resolve();
+      flush
+        .catch(err => {
+          logger.error(`flush local cache failed: ${err}`);
+          setMsgError(msg, err);
+        })
+        .finally(() => postMessage(msg));
+      return true;
+    }
+
     case msgTypes.resetPassword.name: {
-      ensureStr2(msg.params.oldPwd, msg.params.newPwd);
-      repo.resetPassword(msg.params.oldPwd, msg.params.newPwd);
+      const { oldPwd, newPwd, opsLimit, memLimit } = msg.params;
+      ensureStr2(oldPwd, newPwd);
+      if (opsLimit !== undefined) ensureInt(opsLimit);
+      if (memLimit !== undefined) ensureInt(memLimit);
+      deriveKey(() => repo.resetPassword(oldPwd, newPwd, opsLimit, memLimit));
       break;
     }
 
@@ -189,19 +945,27 @@ function repoMsgHandler(msg, msgTypes) {
     case msgTypes.createFile.name: {
       ensureStr(msg.params);
       let file = repo.createFile(msg.params);
-      opened.files[file.ptr] = file;
-      msg.result = file.ptr;
+      const fid = nextObjId();
+      opened.files[fid] = { file, owner: entry, path: msg.params };
+      msg.result = fid;
+      emitChange(entry, {
+        path: msg.params,
+        op: 'create',
+        version: file.currVersion()
+      });
       break;
     }
 
     case msgTypes.openFile.name: {
-      let file;
+      let file, path, created = false;
 
       if (isString(msg.params)) {
-        file = repo.openFile(msg.params);
+        path = msg.params;
+        file = repo.openFile(path);
 
       } else if (isObject(msg.params)) {
         ensureStr(msg.params.path);
+        path = msg.params.path;
 
         let opener = new zbox.OpenOptions();
         let opts = msg.params.opts || {};
@@ -214,14 +978,22 @@ function repoMsgHandler(msg, msgTypes) {
         if (opts.hasOwnProperty('versionLimit')) opener.versionLimit(opts.versionLimit);
         if (opts.hasOwnProperty('dedupChunk')) opener.dedupChunk(opts.dedupChunk);
 
-        file = opener.open(repo, msg.params.path);
+        const existed = (opts.create || opts.createNew) ?
+          repo.pathExists(path) : true;
+        file = opener.open(repo, path);
+        created = !existed;
 
       } else {
-        throw 'Wrong argument, string or Object required';
+        throw new ZboxError('InvalidArgument',
+          'Wrong argument, string or Object required');
       }
 
-      opened.files[file.ptr] = file;
-      msg.result = file.ptr;
+      const fid = nextObjId();
+      opened.files[fid] = { file, owner: entry, path };
+      msg.result = fid;
+      if (created) {
+        emitChange(entry, { path, op: 'create', version: file.currVersion() });
+      }
 
       break;
     }
@@ -229,12 +1001,14 @@ function repoMsgHandler(msg, msgTypes) {
     case msgTypes.createDir.name: {
       ensureStr(msg.params);
       repo.createDir(msg.params);
+      emitChange(entry, { path: msg.params, op: 'create' });
       break;
     }
 
     case msgTypes.createDirAll.name: {
       ensureStr(msg.params);
       repo.createDirAll(msg.params);
+      emitChange(entry, { path: msg.params, op: 'create' });
       break;
     }
 
@@ -244,6 +1018,16 @@ function repoMsgHandler(msg, msgTypes) {
       break;
     }
 
+    case msgTypes.walkDir.name: {
+      const params = isString(msg.params) ? { path: msg.params } : msg.params;
+      ensureStr(params.path);
+      if (params.maxDepth !== undefined) ensureInt(params.maxDepth);
+      if (params.filter !== undefined) ensureStr(params.filter);
+      msg.result = repo.walkDir(params.path, params.maxDepth, params.filter,
+        !!params.includeDirs);
+      break;
+    }
+
     case msgTypes.metadata.name: {
       ensureStr(msg.params);
       msg.result = repo.metadata(msg.params);
@@ -256,54 +1040,161 @@ function repoMsgHandler(msg, msgTypes) {
       break;
     }
 
+    case msgTypes.restoreVersion.name: {
+      ensureStr(msg.params.path);
+      ensureInt(msg.params.num);
+      msg.result = repo.restoreVersion(msg.params.path, msg.params.num);
+      emitChange(entry, {
+        path: msg.params.path,
+        op: 'write',
+        version: msg.result
+      });
+      break;
+    }
+
     case msgTypes.copy.name: {
       ensureStr2(msg.params.from, msg.params.to);
       repo.copy(msg.params.from, msg.params.to);
+      emitChange(entry, { path: msg.params.to, op: 'create' });
       break;
     }
 
     case msgTypes.copyDirAll.name: {
       ensureStr2(msg.params.from, msg.params.to);
-      repo.copyDirAll(msg.params.from, msg.params.to);
+      repo.copyDirAll(msg.params.from, msg.params.to,
+        progressCallback(msg, 'copy'));
+      emitChange(entry, { path: msg.params.to, op: 'create' });
       break;
     }
 
     case msgTypes.removeFile.name: {
       ensureStr(msg.params);
       repo.removeFile(msg.params);
+      emitChange(entry, { path: msg.params, op: 'remove' });
       break;
     }
 
     case msgTypes.removeDir.name: {
       ensureStr(msg.params);
       repo.removeDir(msg.params);
+      emitChange(entry, { path: msg.params, op: 'remove' });
       break;
     }
 
     case msgTypes.removeDirAll.name: {
       ensureStr(msg.params);
-      repo.removeDirAll(msg.params);
+      repo.removeDirAll(msg.params, progressCallback(msg, 'remove'));
+      emitChange(entry, { path: msg.params, op: 'remove' });
       break;
     }
 
     case msgTypes.rename.name: {
       ensureStr2(msg.params.from, msg.params.to);
       repo.rename(msg.params.from, msg.params.to);
+      emitChange(entry, {
+        path: msg.params.from,
+        op: 'rename',
+        to: msg.params.to
+      });
+      break;
+    }
+
+    case msgTypes.importBlob.name: {
+      ensureStr(msg.params.path);
+      ensureBlob(msg.params.blob);
+      let opener = new zbox.OpenOptions();
+      opener.create(true);
+      let file = opener.open(repo, msg.params.path);
+      const importBlob = writeBlob(file, msg.params.blob, msg, entry.cache)
+        .then(() => {
+          emitChange(entry, {
+            path: msg.params.path,
+            op: 'write',
+            version: file.currVersion()
+          });
+        })
+        .finally(() => {
+          file.close();
+          file.free();
+        });
+      replyAsync(msg, importBlob, entry);
+      return true;
+    }
+
+    case msgTypes.exportBlob.name: {
+      const params = isString(msg.params) ? { path: msg.params } : msg.params;
+      ensureStr(params.path);
+      let file = repo.openFile(params.path);
+      const exportBlob = readBlob(file, params.type || '', msg, entry.cache)
+        .finally(() => {
+          file.close();
+          file.free();
+        });
+      replyAsync(msg, exportBlob, entry);
+      return true;
+    }
+
+    case msgTypes.writeFile.name: {
+      ensureStr(msg.params.path);
+      const buf = toBytes(msg.params.data);
+      let opener = new zbox.OpenOptions();
+      opener.create(true);
+      let file = opener.open(repo, msg.params.path);
+      try {
+        if (buf.length > 0) file.writeOnce(buf);
+        truncateRest(file, buf.length);
+        emitChange(entry, {
+          path: msg.params.path,
+          op: 'write',
+          version: file.currVersion()
+        });
+      } finally {
+        file.close();
+        file.free();
+      }
+      break;
+    }
+
+    case msgTypes.batch.name: {
+      msg.result = runBatch(entry, msg, msgTypes);
       break;
     }
   }
 
-  // send message back to main thread
-  postMessage(msg);
+  return false;
+}
+
+// notify file content is changed
+function emitFileChange(entry) {
+  emitChange(entry.owner, {
+    path: entry.path,
+    op: 'write',
+    version: entry.file.currVersion()
+  });
 }
 
 function fileMsgHandler(msg, msgTypes) {
-  let file = opened.files[msg.object];
+  const entry = opened.files[msg.object];
+
+  if (entry === undefined) {
+    throw new ZboxError('Closed', 'File not opened');
+  }
 
-  if (file === undefined) {
-    throw 'File not opened';
+  // file is dropped when its repo is locked
+  if (entry.file === null) {
+    if (msg.type === msgTypes.close.name) {
+      delete opened.files[msg.object];
+      postMessage(msg);
+      return;
+    }
+    ensureUnlocked(entry.owner);
+    throw new ZboxError('Closed', 'File is closed when repo is locked');
   }
 
+  const file = entry.file;
+  useCacheBackend(entry.owner.cache);
+  touchRepo(entry.owner);
+
   // data bytes transfer buffer
   let transBuf = null;
 
@@ -326,46 +1217,103 @@ function fileMsgHandler(msg, msgTypes) {
       break;
     }
 
-    case msgTypes.readAll.name: {
-      let dst = file.readAll();
-      msg.result = dst.buffer;
-      transBuf = [dst.buffer];
+    case msgTypes.readAt.name: {
+      ensureInt(msg.params.len);
+      let buf = new Uint8Array(msg.params.len);
+      const read = file.readAt(toUint64(msg.params.offset), buf);
+      msg.result = {
+        buf: buf.buffer,
+        offset: 0,
+        len: read
+      };
+      transBuf = [buf.buffer];
       break;
     }
 
+    case msgTypes.readAll.name: {
+      replyAsync(msg, readAll(file, msg, entry.owner.cache)
+        .then(dst => dst.buffer), entry.owner);
+      return;
+    }
+
     case msgTypes.readAllString.name: {
-      let dst = file.readAll();
-      msg.result = ab2str(dst.buffer);
-      break;
+      if (msg.params !== undefined) ensureStr(msg.params);
+      const encoding = msg.params;
+      replyAsync(msg, readAll(file, msg, entry.owner.cache)
+        .then(dst => ab2str(dst.buffer, encoding)), entry.owner);
+      return;
     }
 
     case msgTypes.write.name: {
       const buf = new Uint8Array(msg.params.buf, msg.params.offset, msg.params.len);
+      entry.writeFailed = true;
       msg.result = file.write(buf);
+      entry.writeFailed = false;
+      break;
+    }
+
+    case msgTypes.writeAll.name: {
+      const buf = new Uint8Array(msg.params.buf, msg.params.offset, msg.params.len);
+      let written = 0;
+      entry.writeFailed = true;
+      while (written < buf.length) {
+        written += file.write(buf.subarray(written));
+      }
+      entry.writeFailed = false;
       break;
     }
 
     case msgTypes.finish.name: {
+      entry.writeFailed = false;
       file.finish();
+      emitFileChange(entry);
       break;
     }
 
     case msgTypes.writeOnce.name: {
       const buf = new Uint8Array(msg.params.buf, msg.params.offset, msg.params.len);
+
+      // write in chunks if progress is needed or buffer is large, so it can
+      // still be cancelled before writing starts, empty buffer still needs a
+      // single write to create new version
+      if ((msg.progress && buf.length > 0) || buf.length > CHECKPOINT_SIZE) {
+        replyAsync(msg, writeBuffer(file, buf, msg, entry.owner.cache)
+          .then(() => {
+            emitFileChange(entry);
+          }), entry.owner);
+        return;
+      }
+
       msg.result = file.writeOnce(buf);
+      emitFileChange(entry);
+      break;
+    }
+
+    case msgTypes.writeAt.name: {
+      const buf = new Uint8Array(msg.params.buf, msg.params.bufOffset,
+        msg.params.len);
+      msg.result = file.writeAt(toUint64(msg.params.offset), buf);
+      emitFileChange(entry);
       break;
     }
 
+    case msgTypes.toBlob.name: {
+      const type = (msg.params && msg.params.type) || '';
+      replyAsync(msg, readBlob(file, type, msg, entry.owner.cache),
+        entry.owner);
+      return;
+    }
+
     case msgTypes.seek.name: {
       ensureInt(msg.params.from);
-      ensureIntOrNeg(msg.params.offset);
-      msg.result = file.seek(msg.params.from, msg.params.offset);
+      const offset = toInt64(msg.params.offset);
+      msg.result = toSafeNumber(file.seek(msg.params.from, offset));
       break;
     }
 
     case msgTypes.setLen.name: {
-      ensureInt(msg.params);
-      file.setLen(msg.params);
+      file.setLen(toUint64(msg.params));
+      emitFileChange(entry);
       break;
     }
 
@@ -384,11 +1332,19 @@ function fileMsgHandler(msg, msgTypes) {
       break;
     }
 
+    case msgTypes.restoreVersion.name: {
+      ensureInt(msg.params);
+      msg.result = file.restoreVersion(msg.params);
+      emitFileChange(entry);
+      break;
+    }
+
     case msgTypes.versionReader.name: {
       ensureInt(msg.params);
       const vrdr = file.versionReader(msg.params);
-      opened.vrdrs[vrdr.ptr] = vrdr;
-      msg.result = vrdr.ptr;
+      const vid = nextObjId();
+      opened.vrdrs[vid] = { vrdr, owner: entry.owner };
+      msg.result = vid;
       break;
     }
   }
@@ -402,12 +1358,28 @@ function fileMsgHandler(msg, msgTypes) {
 }
 
 function versionReaderMsgHandler(msg, msgTypes) {
-  let vrdr = opened.vrdrs[msg.object];
+  const entry = opened.vrdrs[msg.object];
+
+  if (entry === undefined) {
+    throw new ZboxError('Closed', 'Version reader is closed');
+  }
 
-  if (vrdr == undefined) {
-    throw 'Version reader is closed';
+  // version reader is dropped when its repo is locked
+  if (entry.vrdr === null) {
+    if (msg.type === msgTypes.close.name) {
+      delete opened.vrdrs[msg.object];
+      postMessage(msg);
+      return;
+    }
+    ensureUnlocked(entry.owner);
+    throw new ZboxError('Closed',
+      'Version reader is closed when repo is locked');
   }
 
+  const vrdr = entry.vrdr;
+  useCacheBackend(entry.owner.cache);
+  touchRepo(entry.owner);
+
   // data bytes transfer buffer
   let transBuf = null;
 
@@ -436,23 +1408,37 @@ function versionReaderMsgHandler(msg, msgTypes) {
       break;
     }
 
-    case msgTypes.readAll.name: {
-      let dst = vrdr.readAll();
-      msg.result = dst.buffer;
-      transBuf = [dst.buffer];
+    case msgTypes.readAt.name: {
+      ensureInt(msg.params.len);
+      let buf = new Uint8Array(msg.params.len);
+      const read = vrdr.readAt(toUint64(msg.params.offset), buf);
+      msg.result = {
+        buf: buf.buffer,
+        offset: 0,
+        len: read
+      };
+      transBuf = [buf.buffer];
       break;
     }
 
+    case msgTypes.readAll.name: {
+      replyAsync(msg, readAll(vrdr, msg, entry.owner.cache)
+        .then(dst => dst.buffer), entry.owner);
+      return;
+    }
+
     case msgTypes.readAllString.name: {
-      let dst = vrdr.readAll();
-      msg.result = ab2str(dst.buffer);
-      break;
+      if (msg.params !== undefined) ensureStr(msg.params);
+      const encoding = msg.params;
+      replyAsync(msg, readAll(vrdr, msg, entry.owner.cache)
+        .then(dst => ab2str(dst.buffer, encoding)), entry.owner);
+      return;
     }
 
     case msgTypes.seek.name: {
       ensureInt(msg.params.from);
-      ensureIntOrNeg(msg.params.offset);
-      msg.result = vrdr.seek(msg.params.from, msg.params.offset);
+      const offset = toInt64(msg.params.offset);
+      msg.result = toSafeNumber(vrdr.seek(msg.params.from, offset));
       break;
     }
   }
@@ -469,7 +1455,14 @@ onmessage = function(event) {
   let msg = event.data;
   // console.log(`main -> worker: ${JSON.stringify(msg)}`);
 
-  // reset message result and error
+  // cancel a call, it will be checked between chunks in long operations
+  if (msg.scope === 'cancel') {
+    if (running.has(msg.id)) cancelled.add(msg.id);
+    return;
+  }
+
+  // reset message result and error, message id is kept unchanged so the
+  // main thread can match this message with its pending call
   msg.result = null;
   msg.error = null;
 
@@ -499,7 +1492,8 @@ onmessage = function(event) {
     }
   } catch (err) {
     logger.error(err);
-    msg.error = `${err}`;
+    setMsgError(msg, err);
     postMessage(msg);
+    checkCrash(err);
   }
 };
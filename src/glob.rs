@@ -0,0 +1,42 @@
+// Simple glob pattern matching
+//
+// Supported wildcards:
+//   ?  - matches any single character except '/'
+//   *  - matches any sequence of characters except '/'
+//   ** - matches any sequence of characters including '/', and '**/' can
+//        also match nothing
+pub fn is_match(pattern: &str, text: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let txt: Vec<char> = text.chars().collect();
+    match_at(&pat, &txt)
+}
+
+fn match_at(pat: &[char], txt: &[char]) -> bool {
+    match pat.first() {
+        None => txt.is_empty(),
+        Some(&'*') if pat.get(1) == Some(&'*') => {
+            let rest = &pat[2..];
+            if rest.first() == Some(&'/') && match_at(&rest[1..], txt) {
+                return true;
+            }
+            (0..=txt.len()).any(|i| match_at(rest, &txt[i..]))
+        }
+        Some(&'*') => {
+            let rest = &pat[1..];
+            for i in 0..=txt.len() {
+                if match_at(rest, &txt[i..]) {
+                    return true;
+                }
+                if i < txt.len() && txt[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(&'?') => match txt.first() {
+            Some(&c) if c != '/' => match_at(&pat[1..], &txt[1..]),
+            _ => false,
+        },
+        Some(&c) => txt.first() == Some(&c) && match_at(&pat[1..], &txt[1..]),
+    }
+}
@@ -7,8 +7,9 @@ extern crate wasm_bindgen;
 extern crate web_sys;
 extern crate zbox;
 
-use std::error::Error as StdError;
+use std::convert::TryFrom;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::result;
 use std::str::FromStr;
 use std::time::SystemTime;
@@ -19,6 +20,7 @@ use wasm_bindgen::JsCast;
 
 use zbox::Error;
 
+mod glob;
 mod polyfill;
 mod wasm_logger;
 
@@ -47,19 +49,158 @@ type Result<T> = result::Result<T, JsValue>;
 
 macro_rules! map_js_err {
     ($x:expr) => {
-        $x.map_err(|err| {
-            let desc = err.description().to_owned();
-            let code: i32 = err.into();
-            JsValue::from(format!("ZboxFS({}): {}", code, desc))
-        });
+        $x.map_err(to_js_err);
     };
 }
 
+// create a JS error object with ZboxFS error kind name and code
+fn js_err(kind: &str, code: i32, desc: &str) -> JsValue {
+    let err = js_sys::Error::new(desc);
+    err.set_name("ZboxError");
+    js_sys::Reflect::set(&err, &"kind".into(), &kind.into()).unwrap();
+    js_sys::Reflect::set(&err, &"code".into(), &code.into()).unwrap();
+    err.into()
+}
+
+fn to_js_err(err: Error) -> JsValue {
+    // kind name is the error variant name, without its inner details
+    let kind = format!("{:?}", err);
+    let kind = kind.split('(').next().unwrap();
+    let desc = err.to_string();
+    js_err(kind, err.into(), &desc)
+}
+
+// decrypt failure when deriving volume key means the password is wrong
+fn map_pwd_err<T>(ret: result::Result<T, Error>) -> Result<T> {
+    ret.map_err(|err| {
+        if err == Error::Decrypt {
+            js_err("WrongPassword", err.into(), "Wrong password")
+        } else {
+            to_js_err(err)
+        }
+    })
+}
+
 #[inline]
 fn time_to_u64(t: SystemTime) -> u64 {
     t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
 }
 
+#[inline]
+fn to_ops_limit(n: i32) -> result::Result<zbox::OpsLimit, Error> {
+    match n {
+        0..=2 => Ok(zbox::OpsLimit::from(n)),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+#[inline]
+fn to_mem_limit(n: i32) -> result::Result<zbox::MemLimit, Error> {
+    match n {
+        0..=2 => Ok(zbox::MemLimit::from(n)),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+#[inline]
+fn to_cipher(n: i32) -> result::Result<zbox::Cipher, Error> {
+    match n {
+        0..=1 => Ok(zbox::Cipher::from(n)),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+// resolve seek target to absolute position, it must not be negative or
+// exceed usize::MAX, otherwise ZboxFS would wrap or truncate it
+fn to_seek_pos<S: Seek>(
+    s: &mut S,
+    from: u32,
+    offset: i64,
+    end: usize,
+) -> result::Result<SeekFrom, Error> {
+    let base = match from {
+        0 => 0,
+        1 => end as i128,
+        2 => s.seek(SeekFrom::Current(0))? as i128,
+        _ => return Err(Error::InvalidArgument),
+    };
+    let pos = base + offset as i128;
+    if pos < 0 || pos > usize::MAX as i128 {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(SeekFrom::Start(pos as u64))
+}
+
+// run positional read or write at offset, current position is restored
+// afterwards, even if the operation failed
+fn io_at<S, T, F>(s: &mut S, offset: u64, f: F) -> result::Result<T, Error>
+where
+    S: Seek,
+    F: FnOnce(&mut S) -> result::Result<T, Error>,
+{
+    if offset > usize::MAX as u64 {
+        return Err(Error::InvalidArgument);
+    }
+    let pos = s.seek(SeekFrom::Current(0))?;
+    s.seek(SeekFrom::Start(offset))?;
+    let ret = f(s);
+    let restored = s.seek(SeekFrom::Start(pos));
+    let ret = ret?;
+    restored?;
+    Ok(ret)
+}
+
+// read until buffer is full or reach EOF
+fn read_full<R: Read>(
+    rdr: &mut R,
+    dst: &mut [u8],
+) -> result::Result<usize, Error> {
+    let mut read = 0;
+    while read < dst.len() {
+        match rdr.read(&mut dst[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+// chunk size used when copying version content
+const RESTORE_CHUNK_SIZE: usize = 64 * 1024;
+
+// copy content of a previous version to file as a new current version, chunk
+// by chunk, return the new version number
+//
+// Written content is merged with current content, so if current content is
+// longer than the previous version, it is truncated first as another version.
+// Thus a restore may add two versions, the truncated current content and the
+// restored content.
+fn restore_version(
+    file: &mut zbox::File,
+    ver_num: usize,
+) -> result::Result<usize, Error> {
+    let mut rdr = file.version_reader(ver_num)?;
+    let len = rdr.version()?.content_len();
+
+    io_at(file, 0, |file| {
+        if file.metadata()?.content_len() > len {
+            file.set_len(len)?;
+        }
+        if len > 0 {
+            let mut buf = vec![0u8; RESTORE_CHUNK_SIZE];
+            loop {
+                let read = rdr.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                file.write_all(&buf[..read])?;
+            }
+            file.finish()?;
+        }
+        file.curr_version()
+    })
+}
+
 #[wasm_bindgen]
 pub fn init_env(level: &str) {
     if level != "off" {
@@ -91,6 +232,26 @@ impl RepoOpener {
         RepoOpener { inner }
     }
 
+    #[wasm_bindgen(js_name = opsLimit)]
+    pub fn ops_limit(&mut self, ops_limit: i32) -> Result<()> {
+        let ops_limit = map_js_err!(to_ops_limit(ops_limit))?;
+        self.inner.ops_limit(ops_limit);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = memLimit)]
+    pub fn mem_limit(&mut self, mem_limit: i32) -> Result<()> {
+        let mem_limit = map_js_err!(to_mem_limit(mem_limit))?;
+        self.inner.mem_limit(mem_limit);
+        Ok(())
+    }
+
+    pub fn cipher(&mut self, cipher: i32) -> Result<()> {
+        let cipher = map_js_err!(to_cipher(cipher))?;
+        self.inner.cipher(cipher);
+        Ok(())
+    }
+
     pub fn create(&mut self, create: bool) {
         self.inner.create(create);
     }
@@ -125,7 +286,7 @@ impl RepoOpener {
     }
 
     pub fn open(self, uri: &str, pwd: &str) -> Result<Repo> {
-        let repo = map_js_err!(self.inner.open(uri, pwd))?;
+        let repo = map_pwd_err(self.inner.open(uri, pwd))?;
         Ok(Repo { inner: Some(repo) })
     }
 }
@@ -194,6 +355,9 @@ pub struct RepoInfo {
     pub volumeId: String,
     pub version: String,
     pub uri: String,
+    pub opsLimit: i32,
+    pub memLimit: i32,
+    pub cipher: i32,
     pub compress: bool,
     pub versionLimit: u8,
     pub dedupChunk: bool,
@@ -207,6 +371,9 @@ impl From<zbox::RepoInfo> for RepoInfo {
             volumeId: info.volume_id().to_string(),
             version: info.version(),
             uri: info.uri().to_owned(),
+            opsLimit: info.ops_limit().into(),
+            memLimit: info.mem_limit().into(),
+            cipher: info.cipher().into(),
             compress: info.compress(),
             versionLimit: info.version_limit(),
             dedupChunk: info.dedup_chunk(),
@@ -220,7 +387,7 @@ impl From<zbox::RepoInfo> for RepoInfo {
 #[derive(Serialize)]
 pub struct Metadata {
     pub fileType: String,
-    pub contentLen: usize,
+    pub contentLen: u64,
     pub currVersion: usize,
     pub createdAt: u64,
     pub modifiedAt: u64,
@@ -230,7 +397,7 @@ impl From<zbox::Metadata> for Metadata {
     fn from(md: zbox::Metadata) -> Self {
         Metadata {
             fileType: md.file_type().into(),
-            contentLen: md.content_len(),
+            contentLen: md.content_len() as u64,
             currVersion: md.curr_version(),
             createdAt: time_to_u64(md.created_at()),
             modifiedAt: time_to_u64(md.modified_at()),
@@ -256,11 +423,146 @@ impl From<&zbox::DirEntry> for DirEntry {
     }
 }
 
+// walk directory recursively and collect entries, filter pattern is matched
+// against file name, or against full path if it contains '/'
+fn walk_dir(
+    repo: &zbox::Repo,
+    path: &str,
+    depth: u32,
+    max_depth: Option<u32>,
+    filter: Option<&str>,
+    include_dirs: bool,
+    ret: &mut Vec<DirEntry>,
+) -> result::Result<(), Error> {
+    for ent in repo.read_dir(path)? {
+        let ent_path = ent.path().to_str().unwrap();
+        let is_dir = ent.metadata().is_dir();
+
+        let matched = filter.map_or(true, |pat| {
+            if pat.contains('/') {
+                glob::is_match(pat, ent_path)
+            } else {
+                glob::is_match(pat, ent.file_name())
+            }
+        });
+        if matched && (include_dirs || !is_dir) {
+            ret.push(DirEntry::from(&ent));
+        }
+
+        if is_dir && max_depth.map_or(true, |max| depth < max) {
+            walk_dir(
+                repo,
+                ent_path,
+                depth + 1,
+                max_depth,
+                filter,
+                include_dirs,
+                ret,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// progress of recursive directory operation, reported to JS callback as
+// (done, total) number of entries processed
+struct Progress<'a> {
+    callback: &'a js_sys::Function,
+    done: usize,
+    total: usize,
+}
+
+impl<'a> Progress<'a> {
+    fn new(
+        repo: &zbox::Repo,
+        path: &str,
+        callback: &'a js_sys::Function,
+    ) -> result::Result<Self, Error> {
+        let mut ents = Vec::new();
+        walk_dir(repo, path, 1, None, None, true, &mut ents)?;
+        Ok(Progress {
+            callback,
+            done: 0,
+            total: ents.len(),
+        })
+    }
+
+    fn inc(&mut self) {
+        self.done += 1;
+        let done = JsValue::from_f64(self.done as f64);
+        let total = JsValue::from_f64(self.total as f64);
+        // errors thrown in callback should not abort the operation
+        let _ = self.callback.call2(&JsValue::NULL, &done, &total);
+    }
+}
+
+// same as zbox copy_dir_all, but reports progress for each entry copied
+fn copy_dir_all(
+    repo: &mut zbox::Repo,
+    from: &str,
+    to: &str,
+    progress: &mut Progress,
+) -> result::Result<(), Error> {
+    let to_path = Path::new(to);
+    if Path::new(from) == to_path {
+        return Ok(());
+    }
+    if to_path.starts_with(from) {
+        return Err(Error::InvalidArgument);
+    }
+    if !repo.metadata(from)?.is_dir() {
+        return Err(Error::NotDir);
+    }
+    match repo.metadata(to) {
+        Ok(md) => {
+            if !md.is_dir() {
+                return Err(Error::NotDir);
+            }
+        }
+        Err(Error::NotFound) => repo.create_dir(to)?,
+        Err(err) => return Err(err),
+    }
+
+    for ent in repo.read_dir(from)? {
+        let ent_path = ent.path().to_str().unwrap();
+        let child = to_path.join(ent.file_name());
+        let child = child.to_str().unwrap();
+        if ent.metadata().is_dir() {
+            copy_dir_all(repo, ent_path, child, progress)?;
+        } else {
+            repo.copy(ent_path, child)?;
+        }
+        progress.inc();
+    }
+    Ok(())
+}
+
+// same as zbox remove_dir_all, but reports progress for each entry removed
+fn remove_dir_all(
+    repo: &mut zbox::Repo,
+    path: &str,
+    progress: &mut Progress,
+) -> result::Result<(), Error> {
+    for ent in repo.read_dir(path)? {
+        let ent_path = ent.path().to_str().unwrap();
+        if ent.metadata().is_dir() {
+            remove_dir_all(repo, ent_path, progress)?;
+        } else {
+            repo.remove_file(ent_path)?;
+        }
+        progress.inc();
+    }
+    match repo.remove_dir(path) {
+        Ok(_) | Err(Error::IsRoot) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
 #[allow(non_snake_case)]
 #[derive(Serialize)]
 pub struct Version {
     pub num: usize,
-    pub contentLen: usize,
+    pub contentLen: u64,
     pub createdAt: u64,
 }
 
@@ -268,7 +570,7 @@ impl From<&zbox::Version> for Version {
     fn from(ver: &zbox::Version) -> Self {
         Version {
             num: ver.num(),
-            contentLen: ver.content_len(),
+            contentLen: ver.content_len() as u64,
             createdAt: time_to_u64(ver.created_at()),
         }
     }
@@ -301,6 +603,14 @@ impl VersionReader {
         Ok(read)
     }
 
+    #[wasm_bindgen(js_name = readAt)]
+    pub fn read_at(&mut self, offset: u64, dst: &mut [u8]) -> Result<usize> {
+        map_js_err!(match self.inner {
+            Some(ref mut rdr) => io_at(rdr, offset, |rdr| read_full(rdr, dst)),
+            None => Err(Error::Closed),
+        })
+    }
+
     #[wasm_bindgen(js_name = readAll)]
     pub fn read_all(&mut self) -> Result<js_sys::Uint8Array> {
         let mut buf = Vec::new();
@@ -312,20 +622,14 @@ impl VersionReader {
         Ok(array.slice(0, array.length()))
     }
 
-    pub fn seek(&mut self, from: u32, offset: i32) -> Result<u32> {
-        let new_pos = map_js_err!(match self.inner {
-            Some(ref mut rdr) => {
-                let pos = match from {
-                    0 => SeekFrom::Start(offset as u64),
-                    1 => SeekFrom::End(offset as i64),
-                    2 => SeekFrom::Current(offset as i64),
-                    _ => return map_js_err!(Err(Error::InvalidArgument)),
-                };
+    pub fn seek(&mut self, from: u32, offset: i64) -> Result<u64> {
+        map_js_err!(match self.inner {
+            Some(ref mut rdr) => rdr.version().and_then(|ver| {
+                let pos = to_seek_pos(rdr, from, offset, ver.content_len())?;
                 rdr.seek(pos).map_err(Error::from)
-            }
+            }),
             None => Err(Error::Closed),
-        })?;
-        Ok(new_pos as u32)
+        })
     }
 }
 
@@ -348,6 +652,16 @@ impl File {
         Ok(read)
     }
 
+    #[wasm_bindgen(js_name = readAt)]
+    pub fn read_at(&mut self, offset: u64, dst: &mut [u8]) -> Result<usize> {
+        map_js_err!(match self.inner {
+            Some(ref mut file) => {
+                io_at(file, offset, |file| read_full(file, dst))
+            }
+            None => Err(Error::Closed),
+        })
+    }
+
     #[wasm_bindgen(js_name = readAll)]
     pub fn read_all(&mut self) -> Result<js_sys::Uint8Array> {
         let mut buf = Vec::new();
@@ -385,24 +699,32 @@ impl File {
         Ok(())
     }
 
-    pub fn seek(&mut self, from: u32, offset: i32) -> Result<u32> {
-        let new_pos = map_js_err!(match self.inner {
-            Some(ref mut file) => {
-                let pos = match from {
-                    0 => SeekFrom::Start(offset as u64),
-                    1 => SeekFrom::End(offset as i64),
-                    2 => SeekFrom::Current(offset as i64),
-                    _ => return map_js_err!(Err(Error::InvalidArgument)),
-                };
+    // write at offset as a new version, position is not changed
+    #[wasm_bindgen(js_name = writeAt)]
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+        map_js_err!(match self.inner {
+            Some(ref mut file) => io_at(file, offset, |file| {
+                file.write_once(buf).map(|_| buf.len())
+            }),
+            None => Err(Error::Closed),
+        })
+    }
+
+    pub fn seek(&mut self, from: u32, offset: i64) -> Result<u64> {
+        map_js_err!(match self.inner {
+            Some(ref mut file) => file.metadata().and_then(|md| {
+                let pos = to_seek_pos(file, from, offset, md.content_len())?;
                 file.seek(pos).map_err(Error::from)
-            }
+            }),
             None => Err(Error::Closed),
-        })?;
-        Ok(new_pos as u32)
+        })
     }
 
     #[wasm_bindgen(js_name = setLen)]
-    pub fn set_len(&mut self, len: usize) -> Result<()> {
+    pub fn set_len(&mut self, len: u64) -> Result<()> {
+        let len = map_js_err!(
+            usize::try_from(len).map_err(|_| Error::InvalidArgument)
+        )?;
         map_js_err!(match self.inner {
             Some(ref mut file) => file.set_len(len),
             None => Err(Error::Closed),
@@ -417,6 +739,14 @@ impl File {
         })
     }
 
+    #[wasm_bindgen(js_name = restoreVersion)]
+    pub fn restore_version(&mut self, ver_num: usize) -> Result<usize> {
+        map_js_err!(match self.inner {
+            Some(ref mut file) => restore_version(file, ver_num),
+            None => Err(Error::Closed),
+        })
+    }
+
     #[wasm_bindgen(js_name = versionReader)]
     pub fn version_reader(&self, ver_num: usize) -> Result<VersionReader> {
         let rdr = map_js_err!(match self.inner {
@@ -474,21 +804,26 @@ impl Repo {
         &mut self,
         old_pwd: &str,
         new_pwd: &str,
+        ops_limit: Option<i32>,
+        mem_limit: Option<i32>,
     ) -> Result<()> {
-        map_js_err!(match self.inner {
-            Some(ref mut repo) => repo.reset_password(
-                old_pwd,
-                new_pwd,
-                zbox::OpsLimit::Interactive,
-                zbox::MemLimit::Interactive
-            ),
+        let ops_limit = map_js_err!(
+            ops_limit.map_or(Ok(zbox::OpsLimit::Interactive), to_ops_limit)
+        )?;
+        let mem_limit = map_js_err!(
+            mem_limit.map_or(Ok(zbox::MemLimit::Interactive), to_mem_limit)
+        )?;
+        map_pwd_err(match self.inner {
+            Some(ref mut repo) => {
+                repo.reset_password(old_pwd, new_pwd, ops_limit, mem_limit)
+            }
             None => Err(Error::RepoClosed),
         })
     }
 
     #[wasm_bindgen(js_name = repairSuperBlock)]
     pub fn repair_super_block(uri: &str, pwd: &str) -> Result<()> {
-        map_js_err!(zbox::Repo::repair_super_block(uri, pwd))
+        map_pwd_err(zbox::Repo::repair_super_block(uri, pwd))
     }
 
     #[wasm_bindgen(js_name = pathExists)]
@@ -560,6 +895,35 @@ impl Repo {
         Ok(JsValue::from_serde(&ret).unwrap())
     }
 
+    #[wasm_bindgen(js_name = walkDir)]
+    pub fn walk_dir(
+        &self,
+        path: &str,
+        max_depth: Option<u32>,
+        filter: Option<String>,
+        include_dirs: bool,
+    ) -> Result<JsValue> {
+        // depth starts from 1 at direct children of path
+        if max_depth == Some(0) {
+            return map_js_err!(Err(Error::InvalidArgument));
+        }
+
+        let mut ret = Vec::new();
+        map_js_err!(match self.inner {
+            Some(ref repo) => walk_dir(
+                repo,
+                path,
+                1,
+                max_depth,
+                filter.as_deref(),
+                include_dirs,
+                &mut ret
+            ),
+            None => Err(Error::RepoClosed),
+        })?;
+        Ok(JsValue::from_serde(&ret).unwrap())
+    }
+
     pub fn metadata(&self, path: &str) -> Result<JsValue> {
         let md = map_js_err!(match self.inner {
             Some(ref repo) => repo.metadata(path),
@@ -578,6 +942,22 @@ impl Repo {
         Ok(JsValue::from_serde(&ret).unwrap())
     }
 
+    #[wasm_bindgen(js_name = restoreVersion)]
+    pub fn restore_version(
+        &mut self,
+        path: &str,
+        ver_num: usize,
+    ) -> Result<usize> {
+        map_js_err!(match self.inner {
+            Some(ref mut repo) => zbox::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(repo, path)
+                .and_then(|mut file| restore_version(&mut file, ver_num)),
+            None => Err(Error::RepoClosed),
+        })
+    }
+
     pub fn copy(&mut self, from: &str, to: &str) -> Result<()> {
         map_js_err!(match self.inner {
             Some(ref mut repo) => repo.copy(from, to),
@@ -586,9 +966,20 @@ impl Repo {
     }
 
     #[wasm_bindgen(js_name = copyDirAll)]
-    pub fn copy_dir_all(&mut self, from: &str, to: &str) -> Result<()> {
+    pub fn copy_dir_all(
+        &mut self,
+        from: &str,
+        to: &str,
+        progress: Option<js_sys::Function>,
+    ) -> Result<()> {
         map_js_err!(match self.inner {
-            Some(ref mut repo) => repo.copy_dir_all(from, to),
+            Some(ref mut repo) => match progress {
+                Some(ref callback) => Progress::new(repo, from, callback)
+                    .and_then(|mut prog| {
+                        copy_dir_all(repo, from, to, &mut prog)
+                    }),
+                None => repo.copy_dir_all(from, to),
+            },
             None => Err(Error::RepoClosed),
         })
     }
@@ -610,9 +1001,17 @@ impl Repo {
     }
 
     #[wasm_bindgen(js_name = removeDirAll)]
-    pub fn remove_dir_all(&mut self, path: &str) -> Result<()> {
+    pub fn remove_dir_all(
+        &mut self,
+        path: &str,
+        progress: Option<js_sys::Function>,
+    ) -> Result<()> {
         map_js_err!(match self.inner {
-            Some(ref mut repo) => repo.remove_dir_all(path),
+            Some(ref mut repo) => match progress {
+                Some(ref callback) => Progress::new(repo, path, callback)
+                    .and_then(|mut prog| remove_dir_all(repo, path, &mut prog)),
+                None => repo.remove_dir_all(path),
+            },
             None => Err(Error::RepoClosed),
         })
     }
@@ -11,7 +11,7 @@ const isNodeJs = (typeof process !== 'undefined') && (process.release.name === '
 const TIMEOUT = 60 * 1000;
 
 let uri = 'zbox://jZg7SeR3aJFSmhvKs2ghw7dq@r7kMFaqss3tWhc';
-let uri2 = 'zbox://2c3kbfSqsKYpf36fKKc5YpEY@Rwt6Nh6xesE3n5'; // for Node.js
+let uri2 = 'zbox://2c3kbfSqsKYpf36fKKc5YpEY@Rwt6Nh6xesE3n5'; // second repo
 const pwd = 'pwd';
 
 if (isNodeJs) {
@@ -43,6 +43,20 @@ async function expectError(promise) {
   }
 }
 
+// expect error promise with specified error kind
+async function expectErrorKind(promise, kind) {
+  let error;
+  try {
+    await promise;
+  } catch (err) {
+    error = err;
+  }
+  expect(error).to.be.an('error');
+  expect(error.name).to.equal('ZboxError');
+  expect(error.kind).to.equal(kind);
+  return error;
+}
+
 // ============================================
 // Repo Open/Close Test
 // ============================================
@@ -105,6 +119,13 @@ describe('Repo Open/Close Test', function() {
     await expectError(zbox.openRepo({ uri, pwd: 123 }));
   });
 
+  it('should get wrong password error kind (browser)', async function() {
+    if (isNodeJs) return;
+    await expectErrorKind(zbox.openRepo({ uri, pwd: 'wrong pwd' }),
+      'WrongPassword');
+    await expectErrorKind(zbox.openRepo({ uri, pwd: 123 }), 'InvalidArgument');
+  });
+
   it('should not open repo with createNew flag', async function() {
     await expectError(zbox.openRepo({ uri, pwd, opts: { createNew: true }}));
   });
@@ -134,6 +155,129 @@ describe('Repo Open/Close Test', function() {
     if (repo2) await repo2.close();
   });
 
+  it('should not open repo with invalid cryptos option', async function() {
+    await expectError(zbox.openRepo({ uri, pwd, opts: { opsLimit: 3 }}));
+    await expectError(zbox.openRepo({ uri, pwd, opts: { memLimit: -1 }}));
+    await expectError(zbox.openRepo({ uri, pwd, opts: { cipher: 'aes' }}));
+  });
+
+  it('should not open repo with invalid cache size (browser)', async function() {
+    if (isNodeJs) return;
+    await expectErrorKind(
+      zbox.openRepo({ uri, pwd, opts: { cacheSize: 0 } }),
+      'InvalidArgument'
+    );
+    await expectErrorKind(
+      zbox.openRepo({ uri: uri + '&cache_size=2mb', pwd, opts: { cacheSize: 2 } }),
+      'InvalidArgument'
+    );
+  });
+
+  it('should open repo with large cache size (browser)', async function() {
+    if (isNodeJs) return;
+
+    // cache larger than 32MB, every cached item must stay readable after
+    // reopen, otherwise ZboxFS cache accounting would be broken
+    const opts = { cacheSize: 64 };
+    let repo = await zbox.openRepo({ uri, pwd, opts });
+    const path = `/${Date.now()}`;
+    let file = await repo.createFile(path);
+    await file.writeOnce(new Uint8Array(256 * 1024));
+    await file.close();
+    let stats = await repo.cacheStats();
+    expect(stats.capacity).to.equal(64 * 1024 * 1024);
+    await repo.close();
+
+    repo = await zbox.openRepo({ uri, pwd, opts });
+    const items = (await repo.cacheStats()).items;
+    expect(items).to.equal(stats.items);
+    file = await repo.openFile(path);
+    const result = await file.readAll();
+    expect(result.length).to.equal(256 * 1024);
+    await file.close();
+    stats = await repo.cacheStats();
+    expect(stats.hits).to.be.above(0);
+    await repo.removeFile(path);
+    await repo.close();
+  });
+
+  it('should open two repos at the same time (browser)', async function() {
+    if (isNodeJs) return;
+
+    const uri2b = uri2 + '?cache_type=browser';
+    repo = await zbox.openRepo({ uri, pwd, opts: { create: true }});
+    let repo2 = await zbox.openRepo({ uri: uri2b, pwd, opts: { create: true }});
+
+    const path = `/${Date.now()}`;
+    let file = await repo.createFile(path);
+    await file.writeOnce('foo');
+    await file.close();
+    expect(await repo.pathExists(path)).to.be.true;
+    expect(await repo2.pathExists(path)).to.be.false;
+
+    await repo2.close();
+    expect(await repo.isFile(path)).to.be.true;
+    await repo.close();
+  });
+
+  it('should open memory repo', async function() {
+    const memUri = `mem://${Date.now()}`;
+    let memRepo = await zbox.openRepo({ uri: memUri, pwd, opts: { create: true } });
+    let file = await memRepo.createFile('/foo');
+    await file.writeOnce('foo');
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    const result = await file.readAllString();
+    expect(result).to.equal('foo');
+    await file.close();
+    await memRepo.close();
+  });
+
+  it('should not open repo with unsupported uri scheme (browser)', async function() {
+    if (isNodeJs) return;
+    await expectErrorKind(zbox.openRepo({ uri: 'foo://bar', pwd }), 'InvalidUri');
+    await expectErrorKind(zbox.openRepo({ uri: 'idb://bar', pwd }), 'InvalidUri');
+    await expectErrorKind(
+      zbox.openRepo({ uri: 'mem://bar', pwd, opts: { cacheSize: 2 } }),
+      'InvalidArgument'
+    );
+  });
+
+  it('should lock repo when it is opened (browser)', async function() {
+    if (isNodeJs) return;
+
+    let repo = await zbox.openRepo({ uri, pwd });
+    await expectErrorKind(zbox.openRepo({ uri, pwd }), 'RepoOpened');
+
+    // wait for the lock to be released
+    setTimeout(() => repo.close(), 100);
+    repo = await zbox.openRepo({ uri, pwd, opts: { lockTimeout: 10000 } });
+    await repo.close();
+  });
+
+  it('should auto lock repo after inactivity (browser)', async function() {
+    if (isNodeJs) return;
+
+    let repo = await zbox.openRepo({ uri, pwd, opts: { autoLockAfter: 200 } });
+    let file = await repo.createFile(`/${Date.now()}`);
+    await new Promise(resolve => setTimeout(resolve, 500));
+
+    await expectErrorKind(repo.info(), 'Locked');
+    await expectErrorKind(file.metadata(), 'Locked');
+    await expectErrorKind(repo.unlock('wrong pwd'), 'WrongPassword');
+    await repo.unlock(pwd);
+
+    const info = await repo.info();
+    expect(info).to.be.an('object');
+    await expectErrorKind(file.metadata(), 'Closed');
+
+    // new file never takes over handle of the dropped one
+    const file2 = await repo.createFile(`/${Date.now()}-2`);
+    await expectErrorKind(file.metadata(), 'Closed');
+    await file2.close();
+    await file.close();
+    await repo.close();
+  });
+
   it('should open repo in read-only', async function() {
     repo = await zbox.openRepo({ uri, pwd, opts: { readOnly: true }});
     await expectError(repo.createFile('/foo'));
@@ -141,13 +285,96 @@ describe('Repo Open/Close Test', function() {
   });
 
   it('should exit zbox', async function() {
-    if (zbox) await zbox.exit();
+    if (isNodeJs) {
+      if (zbox) await zbox.exit();
+      return;
+    }
+
+    // exit will finish and close opened files and repo
+    const path = `/${Date.now()}`;
+    let repo = await zbox.openRepo({ uri, pwd });
+    let file = await repo.createFile(path);
+    await file.write(new Uint8Array([1, 2, 3]));
+    await zbox.exit({ timeout: 10000 });
+
+    // objects created before exit are invalid
+    await expectErrorKind(file.metadata(), 'Closed');
+    await expectErrorKind(repo.info(), 'Closed');
+  });
+
+  it('should reject pending calls on force exit (browser)', async function() {
+    if (isNodeJs) return;
+
+    await zbox.initEnv({ log: { level: 'debug' } });
+    const repo = await zbox.openRepo({ uri, pwd, opts: { create: true }});
+    const pending = repo.info();
+    await zbox.exit({ force: true });
+    await expectErrorKind(pending, 'Closed');
+    await expectErrorKind(repo.info(), 'Closed');
   });
 
   it('should not open repo again after exit', async function() {
     await expectError(zbox.openRepo({ uri, pwd, opts: { create: true }}));
   });
 
+  // start worker again and return it
+  async function restartWorker() {
+    const OrigWorker = window.Worker;
+    let worker;
+    window.Worker = class extends OrigWorker {
+      constructor(...args) {
+        super(...args);
+        worker = this;
+      }
+    };
+    try {
+      await zbox.exit();
+      await zbox.initEnv({ log: { level: 'debug' } });
+    } finally {
+      window.Worker = OrigWorker;
+    }
+    return worker;
+  }
+
+  it('should reject pending calls on crash message (browser)', async function() {
+    if (isNodeJs) return;
+
+    const worker = await restartWorker();
+    const repo = await zbox.openRepo({ uri, pwd, opts: { create: true }});
+    const pending = repo.info();
+
+    // simulate the message sent by worker after wasm trap
+    worker.onmessage({
+      data: {
+        scope: 'crash',
+        error: { name: 'RuntimeError', message: 'unreachable' }
+      }
+    });
+    await expectErrorKind(pending, 'WorkerCrashed');
+    await expectErrorKind(repo.info(), 'WorkerCrashed');
+    await expectErrorKind(repo.close(), 'WorkerCrashed');
+  });
+
+  it('should reject pending calls on worker error (browser)', async function() {
+    if (isNodeJs) return;
+
+    const worker = await restartWorker();
+    const repo = await zbox.openRepo({ uri, pwd, opts: { create: true }});
+    const file = await repo.createFile(`/${Date.now()}`);
+    const pending = file.metadata();
+
+    // simulate uncaught error in worker
+    worker.onerror({ message: 'uncaught error', preventDefault() {} });
+    await expectErrorKind(pending, 'WorkerCrashed');
+    await expectErrorKind(file.metadata(), 'WorkerCrashed');
+    await expectErrorKind(repo.info(), 'WorkerCrashed');
+
+    // repo can be opened again after worker is restarted
+    await zbox.initEnv({ log: { level: 'debug' } });
+    const repo2 = await zbox.openRepo({ uri, pwd });
+    await repo2.close();
+  });
+
   after(async function() {});
 });
 
@@ -260,6 +487,64 @@ describe('File IO Test', function() {
       });
   });
 
+  it(`should read as readable stream (browser)`, async function() {
+    if (isNodeJs) return;
+
+    let file = await repo.openFile({ path: filePath, opts: { read: true } });
+    const reader = file.readable({ chunkSize: 2 }).getReader();
+    let chunks = [];
+    while (true) {
+      const { done, value } = await reader.read();
+      if (done) break;
+      chunks.push(value);
+    }
+    expect(chunks).to.eql([new Uint8Array([1, 2]), new Uint8Array([3])]);
+    await file.close();
+  });
+
+  it(`should write from readable stream (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+    let file = await repo.createFile(path);
+    const body = new Response(new Blob([buf, buf2])).body;
+    await body.pipeTo(file.writable());
+
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    let result = await file.readAll();
+    expect(result).to.eql(new Uint8Array([1, 2, 3, 4, 5, 6]));
+
+    // pipe version content through stream
+    const ver = await file.currVersion();
+    let vrdr = await file.versionReader(ver);
+    result = await new Response(vrdr.readable()).arrayBuffer();
+    expect(new Uint8Array(result)).to.eql(new Uint8Array([1, 2, 3, 4, 5, 6]));
+    await vrdr.close();
+    await file.close();
+
+    // large chunk is written completely
+    const big = new Uint8Array(1024 * 1024).map((_, i) => i % 256);
+    file = await repo.createFile(`${path}-big`);
+    let writer = file.writable().getWriter();
+    await writer.write(big.slice());
+    await writer.close();
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    result = await file.readAll();
+    expect(result).to.eql(big);
+
+    // aborted stream restores previous content and ends its write
+    writer = file.writable().getWriter();
+    await writer.write(new Uint8Array([9, 9]));
+    await writer.abort();
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    result = await file.readAll();
+    expect(result).to.eql(big);
+    await repo.createDir(`${path}-dir`);
+    await repo.removeDir(`${path}-dir`);
+    await file.close();
+    await repo.removeFile(`${path}-big`);
+  });
+
   it(`should write to file in parts again`, async function() {
 
     let file = await repo.openFile({ path: filePath, opts: { write: true } });
@@ -293,6 +578,18 @@ describe('File IO Test', function() {
     await file.close();
   });
 
+  it(`should read two files concurrently`, async function() {
+    let file = await repo.openFile({ path: filePath, opts: { read: true } });
+    let file2 = await repo.openFile({ path: filePath, opts: { read: true } });
+    const [result, result2] = await Promise.all([
+      file.readAll(),
+      file2.read(new Uint8Array(2))
+    ]);
+    expect(result).to.eql(buf2);
+    expect(result2).to.eql(buf2.slice(0, 2));
+    await Promise.all([file.close(), file2.close()]);
+  });
+
   it(`should get current version of file`, async function() {
     let file = await repo.openFile({ path: filePath, opts: { read: true } });
     let ver = await file.currVersion();
@@ -409,6 +706,108 @@ describe('File IO Test', function() {
     await file.close();
   });
 
+  it(`should read and write at offset without moving position (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+    let file = await repo.openFile({ path, opts: { create: true } });
+    await file.writeOnce(new Uint8Array([1, 2, 3, 4]));
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 1 });
+
+    let result = await file.readAt(2, 10);
+    expect(result).to.eql(new Uint8Array([3, 4]));
+    result = await file.writeAt(3, new Uint8Array([5, 6]));
+    expect(result).to.equal(2);
+    result = await file.writeAt({ offset: 0, data: 'a' });
+    expect(result).to.equal(1);
+    expect(await file.currVersion()).to.equal(4);
+
+    // position is not changed
+    result = await file.read(new Uint8Array(1));
+    expect(result).to.eql(new Uint8Array([2]));
+    result = await file.readAt(0, 5);
+    expect(result).to.eql(new Uint8Array([97, 2, 3, 5, 6]));
+
+    let vrdr = await file.versionReader(2);
+    result = await vrdr.readAt(1, 2);
+    expect(result).to.eql(new Uint8Array([2, 3]));
+    await vrdr.close();
+
+    await expectError(file.readAt(0, -1));
+    await expectError(file.writeAt(0, 42));
+    await file.close();
+  });
+
+  it(`should restore previous version (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+    let file = await repo.openFile({ path, opts: { create: true } });
+    await file.writeOnce(new Uint8Array([1, 2, 3]));
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    await file.writeOnce(new Uint8Array([4, 5, 6, 7]));
+    expect(await file.currVersion()).to.equal(3);
+
+    // longer current content is truncated first as another version
+    let ver = await file.restoreVersion(2);
+    expect(ver).to.equal(await file.currVersion());
+    expect(ver).to.equal(5);
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    let result = await file.readAll();
+    expect(result).to.eql(new Uint8Array([1, 2, 3]));
+    let hist = await file.history();
+    expect(hist.map(v => v.num)).to.eql([1, 2, 3, 4, 5]);
+    expect(hist.map(v => v.contentLen)).to.eql([0, 3, 4, 3, 3]);
+    let vrdr = await file.versionReader(4);
+    expect(await vrdr.readAll()).to.eql(new Uint8Array([4, 5, 6]));
+    await vrdr.close();
+    await file.close();
+
+    ver = await repo.restoreVersion(path, 3);
+    file = await repo.openFile(path);
+    expect(await file.currVersion()).to.equal(ver);
+    result = await file.readAll();
+    expect(result).to.eql(new Uint8Array([4, 5, 6, 7]));
+
+    // shorter current content is overwritten as one version
+    hist = await file.history();
+    expect(hist[hist.length - 1]).to.include({ num: 6, contentLen: 4 });
+    await file.close();
+
+    await expectError(repo.restoreVersion(path, 100));
+    await repo.removeFile(path);
+  });
+
+  it(`should able to use BigInt for seek and length (browser)`, async function() {
+    if (isNodeJs) return;
+
+    let file = await repo.openFile({ path: filePath, opts: { write: true } });
+    let newPos = await file.seek({ from: Zbox.SeekFrom.Start, offset: 3n });
+    expect(newPos).to.equal(3);
+    await file.setLen(4n);
+
+    // position must not be negative or exceed wasm32 address range
+    await expectErrorKind(file.seek({ from: Zbox.SeekFrom.End, offset: -5 }),
+      'InvalidArgument');
+    await expectErrorKind(file.seek({ from: Zbox.SeekFrom.Current, offset: -4 }),
+      'InvalidArgument');
+    await expectErrorKind(file.seek({ from: Zbox.SeekFrom.Start, offset: 2 ** 32 }),
+      'InvalidArgument');
+    await expectErrorKind(file.readAt(2 ** 32, 1), 'InvalidArgument');
+    newPos = await file.seek({ from: Zbox.SeekFrom.Current, offset: 0 });
+    expect(newPos).to.equal(3);
+    await file.close();
+  });
+
+  it(`should not seek or set length beyond safe integer`, async function() {
+    let file = await repo.openFile({ path: filePath, opts: { write: true } });
+    await expectError(file.seek({ from: Zbox.SeekFrom.Start, offset: -1 }));
+    await expectError(file.seek({ from: Zbox.SeekFrom.Start, offset: 2 ** 53 }));
+    await expectError(file.seek({ from: Zbox.SeekFrom.Start, offset: 1.5 }));
+    await expectError(file.setLen(-1));
+    await file.close();
+  });
+
   it(`should able to read and write string to file`, async function() {
     const path = `/${Date.now()}`;
     const str = 'foo bar';
@@ -423,6 +822,217 @@ describe('File IO Test', function() {
     await file.close();
   });
 
+  it(`should read and write string as UTF-8`, async function() {
+    const path = `/${Date.now()}`;
+    const str = 'h\u00e9llo \u4e16\u754c \ud83d\ude00';
+
+    let file = await repo.openFile({ path, opts: { create: true } });
+    await file.writeOnce(str);
+
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    let result = await file.readAll();
+    expect(Array.from(result)).to.eql(Array.from(new TextEncoder().encode(str)));
+
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    result = await file.readAllString();
+    expect(result).to.equal(str);
+
+    await file.close();
+  });
+
+  it(`should read string with specified encoding (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+
+    let file = await repo.openFile({ path, opts: { create: true } });
+    await file.writeOnce(new Uint8Array([0x68, 0x00, 0xe9, 0x00]));
+
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    let result = await file.readAllString('utf-16le');
+    expect(result).to.equal('h\u00e9');
+
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    await expectErrorKind(file.readAllString('no-such-encoding'),
+      'InvalidArgument');
+
+    await file.close();
+  });
+
+  it(`should import and export blob (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+    const content = new Uint8Array(100 * 1024).map((_, i) => i % 256);
+
+    await repo.importBlob(path, new Blob([content]));
+    let blob = await repo.exportBlob({ path, type: 'text/plain' });
+    expect(blob).to.be.an.instanceof(Blob);
+    expect(blob.type).to.equal('text/plain');
+    let result = new Uint8Array(await blob.arrayBuffer());
+    expect(result).to.eql(content);
+
+    // import again will replace file content
+    await repo.importBlob(path, new Blob([buf]));
+    let file = await repo.openFile(path);
+    blob = await file.toBlob();
+    expect(blob.size).to.equal(buf.length);
+    result = new Uint8Array(await blob.arrayBuffer());
+    expect(result).to.eql(buf);
+    await file.close();
+
+    // import content of the same size only adds one version
+    const ver = (await repo.metadata(path)).currVersion;
+    await repo.importBlob(path, new Blob([buf2]));
+    expect((await repo.metadata(path)).currVersion).to.equal(ver + 1);
+    blob = await repo.exportBlob(path);
+    expect(new Uint8Array(await blob.arrayBuffer())).to.eql(buf2);
+
+    // import empty blob
+    await repo.importBlob(path, new Blob([]));
+    blob = await repo.exportBlob(path);
+    expect(blob.size).to.equal(0);
+
+    await expectErrorKind(repo.importBlob(path, 'not blob'),
+      'InvalidArgument');
+  });
+
+  it(`should notify repo changes (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const dir = `/${Date.now()}`;
+    const path = `${dir}/foo`;
+    let changes = [];
+    await repo.createDir(dir);
+    const unwatch = repo.watch(dir, change => changes.push(change));
+
+    let file = await repo.createFile(path);
+    await file.writeOnce('foo');
+    await file.close();
+    await repo.rename({ from: path, to: `${dir}/bar` });
+    await repo.removeFile(`${dir}/bar`);
+
+    expect(changes.map(c => c.op)).to.eql([
+      'create', 'write', 'rename', 'remove'
+    ]);
+    expect(changes[0].path).to.equal(path);
+    expect(changes[1].version).to.equal(2);
+    expect(changes[2].to).to.equal(`${dir}/bar`);
+
+    // watchers in other browser contexts don't need to open the repo
+    let unwatchRepo;
+    const remote = new Promise(resolve => {
+      unwatchRepo = zbox.watchRepo(uri, resolve);
+    });
+    await repo.createDir(`${dir}/baz`);
+    const change = await remote;
+    expect(change).to.eql({ path: `${dir}/baz`, op: 'create' });
+    unwatchRepo();
+    expect(() => zbox.watchRepo('foo://bar', () => {})).to.throw();
+
+    unwatch();
+    await repo.removeDir(`${dir}/baz`);
+    await repo.removeDir(dir);
+    expect(changes.length).to.equal(5);
+  });
+
+  it(`should abort calls (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const path = `/${Date.now()}`;
+    let ctl = new AbortController();
+    ctl.abort();
+    await expectErrorKind(repo.isFile(path, { signal: ctl.signal }),
+      'AbortError');
+
+    // abort a long running import
+    ctl = new AbortController();
+    const blob = new Blob([new Uint8Array(8 * 1024 * 1024)]);
+    const importing = repo.importBlob(path, blob, { signal: ctl.signal });
+    ctl.abort();
+    await expectErrorKind(importing, 'AbortError');
+
+    // aborted import must not leave a write open in the worker
+    await repo.createDir(`${path}-dir`);
+    let file2 = await repo.createFile(`${path}-dir/foo`);
+    await file2.writeOnce(new Uint8Array([1, 2, 3]));
+    await file2.close();
+    await repo.removeDirAll(`${path}-dir`);
+
+    // call options on method without parameter
+    let file = await repo.openFile({ path, opts: { create: true } });
+    const result = await file.readAll({ timeout: 10000 });
+    expect(result).to.be.an('uint8array');
+
+    // call options on method with optional parameter
+    ctl = new AbortController();
+    await file.seek({ from: Zbox.SeekFrom.Start, offset: 0 });
+    const str = await file.readAllString({ signal: ctl.signal });
+    expect(str).to.be.a('string');
+    const blob2 = await file.toBlob({ timeout: 10000 });
+    expect(blob2).to.be.an.instanceof(Blob);
+    await file.close();
+
+    // file opened by cancelled call is closed in worker
+    ctl = new AbortController();
+    const opening = repo.openFile({ path }, { signal: ctl.signal });
+    ctl.abort();
+    await expectErrorKind(opening, 'AbortError');
+    file = await repo.openFile(path);
+    await file.close();
+  });
+
+  it(`should report progress (browser)`, async function() {
+    if (isNodeJs) return;
+
+    const dir = `/${Date.now()}`;
+    const path = `${dir}/foo`;
+    const size = 3 * 1024 * 1024;
+    await repo.createDir(dir);
+
+    let events = [];
+    const onProgress = p => events.push(p);
+    await repo.importBlob(path, new Blob([new Uint8Array(size)]),
+      { onProgress });
+    expect(events.length).to.be.above(2);
+    expect(events.every(p => p.phase === 'write')).to.be.true;
+    expect(events[events.length - 1]).to.eql({
+      phase: 'write', done: size, total: size
+    });
+
+    events = [];
+    let file = await repo.openFile(path);
+    const result = await file.readAll({ onProgress });
+    expect(result.length).to.equal(size);
+    expect(events[events.length - 1]).to.eql({
+      phase: 'read', done: size, total: size
+    });
+    await file.close();
+
+    events = [];
+    await repo.copyDirAll({ from: dir, to: `${dir}-copy` }, { onProgress });
+    expect(events).to.eql([{ phase: 'copy', done: 1, total: 1 }]);
+
+    events = [];
+    await repo.removeDirAll(`${dir}-copy`, { onProgress });
+    expect(events).to.eql([{ phase: 'remove', done: 1, total: 1 }]);
+
+    events = [];
+    file = await repo.openFile({ path, opts: { write: true } });
+    await file.writeOnce(new Uint8Array(size), { onProgress });
+    expect(events.length).to.be.above(2);
+    expect(events[events.length - 1]).to.eql({
+      phase: 'write', done: size, total: size
+    });
+
+    // concurrent write is not interleaved into a running chunked write
+    const writing = file.writeOnce(new Uint8Array(size), { onProgress });
+    await repo.createDir(`${dir}/bar`);
+    await writing;
+    await file.close();
+    await repo.removeDirAll(dir);
+  });
+
   it(`should able to run API reference doc example #1`, async function() {
     const buf = new Uint8Array([1, 2, 3, 4, 5, 6]);
     const path = `/${Date.now()}`;
@@ -503,6 +1113,14 @@ describe('Dir IO Test', function() {
     expect(result).to.be.false;
   });
 
+  it('should get not found error kind (browser)', async function() {
+    if (isNodeJs) return;
+    const err = await expectErrorKind(repo.readDir('/non-exist'), 'NotFound');
+    expect(err.code).to.be.a('number');
+    expect(err.op).to.equal('repo.readDir');
+    expect(err.path).to.equal('/non-exist');
+  });
+
   it('should not read dir with wrong argument', async function() {
     await expectError(repo.readDir(123));
     await expectError(repo.readDir({}));
@@ -550,6 +1168,40 @@ describe('Dir IO Test', function() {
     expect(ent.metadata).to.be.an('object');
   });
 
+  it('should walk dir recursively (browser)', async function() {
+    if (isNodeJs) return;
+
+    const filePath = '/1/2/walk.txt';
+    let file = await repo.createFile(filePath);
+    await file.close();
+
+    let ents = await repo.walkDir('/1');
+    expect(ents.map(ent => ent.path)).to.eql([filePath]);
+
+    ents = await repo.walkDir('/1', { includeDirs: true });
+    let paths = ents.map(ent => ent.path);
+    expect(paths).to.include.members(['/1/2', '/1/2/3', dirPath2, filePath]);
+    expect(paths.length).to.equal(4);
+    expect(ents[0].metadata).to.be.an('object');
+
+    ents = await repo.walkDir({ path: '/1', maxDepth: 2, includeDirs: true });
+    paths = ents.map(ent => ent.path);
+    expect(paths).to.include.members(['/1/2', '/1/2/3', filePath]);
+    expect(paths.length).to.equal(3);
+    await expectErrorKind(repo.walkDir({ path: '/1', maxDepth: 0 }),
+      'InvalidArgument');
+
+    ents = await repo.walkDir('/', { filter: '*.txt' });
+    expect(ents.map(ent => ent.path)).to.include(filePath);
+    ents = await repo.walkDir('/', { filter: '/1/**/*.txt' });
+    expect(ents.map(ent => ent.path)).to.eql([filePath]);
+    ents = await repo.walkDir('/', { filter: '*.pdf' });
+    expect(ents.length).to.equal(0);
+
+    await expectError(repo.walkDir('/non-exists'));
+    await repo.removeFile(filePath);
+  });
+
   it('should not remove root dir', async function() {
     await expectError(repo.removeDir('/'));
   });
@@ -617,6 +1269,9 @@ describe('FS Test', function() {
     expect(info.volumeId).to.be.an('string');
     expect(info.version).to.be.an('string');
     expect(info.uri).to.be.an('string');
+    expect(info.opsLimit).to.equal(Zbox.OpsLimit.Interactive);
+    expect(info.memLimit).to.equal(Zbox.MemLimit.Interactive);
+    expect(info.cipher).to.be.oneOf([Zbox.Cipher.Xchacha, Zbox.Cipher.Aes]);
     expect(info.compress).to.be.false;
     expect(info.versionLimit).to.be.a('number');
     expect(info.dedupChunk).to.be.true;
@@ -624,6 +1279,64 @@ describe('FS Test', function() {
     expect(info.createdAt).to.be.a('number');
   });
 
+  it('should persist local cache before repo close (browser)', async function() {
+    if (isNodeJs) return;
+
+    let file = await repo.createFile(`/${Date.now()}`);
+    await file.writeOnce('cache me');
+    await file.close();
+    await repo.flushCache();
+
+    // local cache db can be read from main thread while repo is still open
+    const repoId = /@(\w+)/.exec(uri)[1];
+    const cnt = await new Promise((resolve, reject) => {
+      let req = indexedDB.open(`zbox_local_cache_${repoId}`);
+      req.onerror = reject;
+      req.onsuccess = (event) => {
+        let db = event.target.result;
+        let countReq = db.transaction('cache').objectStore('cache').count();
+        countReq.onerror = reject;
+        countReq.onsuccess = () => {
+          db.close();
+          resolve(countReq.result);
+        };
+      };
+    });
+    expect(cnt).to.be.above(0);
+  });
+
+  it('should get local cache statistics (browser)', async function() {
+    if (isNodeJs) return;
+
+    let file = await repo.createFile(`/${Date.now()}`);
+    await file.writeOnce('cache stats');
+    await file.close();
+
+    const stats = await repo.cacheStats();
+    expect(stats.items).to.be.above(0);
+    expect(stats.bytes).to.be.above(0);
+    expect(stats.bytes).to.be.at.most(stats.capacity);
+    expect(stats.capacity).to.equal(1024 * 1024);
+    expect(stats.hits).to.be.a('number');
+    expect(stats.misses).to.be.a('number');
+  });
+
+  it('should not use opened repo cache for other repo (browser)', async function() {
+    if (isNodeJs) return;
+
+    await repo.flushCache();
+    const stats = await repo.cacheStats();
+    const result = await zbox.exists(uri2);
+    expect(result).to.be.a('boolean');
+    expect((await repo.cacheStats()).items).to.equal(stats.items);
+  });
+
+  it('should not reset password with invalid cryptos option', async function() {
+    await expectError(repo.resetPassword({ oldPwd: pwd }));
+    await expectError(repo.resetPassword({ oldPwd: pwd, newPwd, opsLimit: 3 }));
+    await expectError(repo.resetPassword({ oldPwd: pwd, newPwd, memLimit: -1 }));
+  });
+
   it('should run repo.resetPassword()', async function() {
     if (isNodeJs) {
       await repo.resetPassword({
@@ -659,6 +1372,23 @@ describe('FS Test', function() {
     }
   });
 
+  it('should reset password with cryptos option (browser)', async function() {
+    if (isNodeJs) return;
+
+    await repo.resetPassword({
+      oldPwd: pwd,
+      newPwd,
+      opsLimit: Zbox.OpsLimit.Moderate,
+      memLimit: Zbox.MemLimit.Interactive
+    });
+    await repo.resetPassword({
+      oldPwd: newPwd,
+      newPwd: pwd,
+      opsLimit: Zbox.OpsLimit.Interactive,
+      memLimit: Zbox.MemLimit.Interactive
+    });
+  });
+
   it('should check path exists', async function() {
     let result;
     result = await repo.pathExists("/");
@@ -667,6 +1397,84 @@ describe('FS Test', function() {
     expect(result).to.be.false;
   });
 
+  it('should run repo calls concurrently', async function() {
+    const [exists, isDir, dirs, md] = await Promise.all([
+      repo.pathExists('/'),
+      repo.isDir('/'),
+      repo.readDir('/'),
+      repo.metadata('/')
+    ]);
+    expect(exists).to.be.true;
+    expect(isDir).to.be.true;
+    expect(dirs).to.be.an('array');
+    expect(md.fileType).to.equal('Dir');
+  });
+
+  it('should run repo calls in batch (browser)', async function() {
+    if (isNodeJs) return;
+
+    const dir = `/${Date.now()}`;
+    let results = await repo.batch([
+      { type: 'createDir', params: dir },
+      { type: 'createFile', params: `${dir}/foo` },
+      { type: 'isFile', params: `${dir}/foo` },
+      { type: 'createDir', params: dir },
+      { type: 'close' },
+      { type: 'metadata', params: dir }
+    ]);
+    expect(results.length).to.equal(6);
+    expect(results[0]).to.eql({ result: null });
+    expect(results[1].result).to.be.an('object');
+    expect(results[2].result).to.be.true;
+    expect(results[3].error.name).to.equal('ZboxError');
+    expect(results[3].error.kind).to.equal('AlreadyExists');
+    expect(results[4].error.kind).to.equal('InvalidArgument');
+    expect(results[5].result.fileType).to.equal('Dir');
+    await results[1].result.close();
+
+    // stop on first error
+    results = await repo.batch([
+      { type: 'removeFile', params: `${dir}/bar` },
+      { type: 'removeFile', params: `${dir}/foo` }
+    ], { stopOnError: true });
+    expect(results.length).to.equal(1);
+    expect(results[0].error.kind).to.equal('NotFound');
+    expect(await repo.isFile(`${dir}/foo`)).to.be.true;
+
+    // write files in batch
+    results = await repo.batch([
+      { type: 'writeFile', params: { path: `${dir}/foo`, data: 'foo' } },
+      {
+        type: 'writeFile',
+        params: { path: `${dir}/bar`, data: new Uint8Array([1, 2, 3]) }
+      },
+      { type: 'writeFile', params: { path: `${dir}/baz`, data: 123 } }
+    ]);
+    expect(results[0]).to.eql({ result: null });
+    expect(results[1]).to.eql({ result: null });
+    expect(results[2].error.kind).to.equal('InvalidArgument');
+    let file = await repo.openFile(`${dir}/foo`);
+    expect(await file.readAllString()).to.equal('foo');
+    await file.close();
+    file = await repo.openFile(`${dir}/bar`);
+    expect(Array.from(await file.readAll())).to.eql([1, 2, 3]);
+    await file.close();
+
+    // write file directly
+    await repo.writeFile(`${dir}/bar`, 'x');
+    file = await repo.openFile(`${dir}/bar`);
+    expect(await file.readAllString()).to.equal('x');
+    await file.close();
+
+    // content of the same size is written as one version
+    const ver = (await repo.metadata(`${dir}/bar`)).currVersion;
+    await repo.writeFile(`${dir}/bar`, 'y');
+    expect((await repo.metadata(`${dir}/bar`)).currVersion).to.equal(ver + 1);
+
+    await repo.removeDirAll(dir);
+    await expectErrorKind(repo.batch({ ops: 'foo' }), 'InvalidArgument');
+  });
+
   it('should not check path with wrong argument', async function() {
     await expectError(repo.pathExists(123));
     await expectError(repo.pathExists([]));